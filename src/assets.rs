@@ -0,0 +1,202 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use crate::{
+    gltf_file::{load_gltf, GltfError},
+    obj::{load_objs, Mesh, ObjError},
+    ply::{load_ply, PlyError},
+    stl::{load_stl, StlError},
+};
+
+/// A reference counted handle to the models of a mesh file.
+/// Cloning it is cheap and every clone shares the same vertex data.
+pub type ObjHandle = Arc<Vec<Mesh>>;
+
+/// The error of the loader of whatever format a mesh file has.
+#[derive(Debug)]
+pub enum MeshError {
+    Obj(ObjError),
+    Gltf(GltfError),
+    Stl(StlError),
+    Ply(PlyError),
+}
+
+impl Display for MeshError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MeshError::Obj(error) => write!(f, "{error}"),
+            MeshError::Gltf(error) => write!(f, "{error}"),
+            MeshError::Stl(error) => write!(f, "{error}"),
+            MeshError::Ply(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for MeshError {}
+
+impl From<ObjError> for MeshError {
+    fn from(error: ObjError) -> Self {
+        MeshError::Obj(error)
+    }
+}
+
+impl From<GltfError> for MeshError {
+    fn from(error: GltfError) -> Self {
+        MeshError::Gltf(error)
+    }
+}
+
+impl From<StlError> for MeshError {
+    fn from(error: StlError) -> Self {
+        MeshError::Stl(error)
+    }
+}
+
+impl From<PlyError> for MeshError {
+    fn from(error: PlyError) -> Self {
+        MeshError::Ply(error)
+    }
+}
+
+/// Loads the models of a mesh file, picking the loader by its extension.
+/// `.gltf` and `.glb` files are loaded as glTF, `.stl` as STL, `.ply` as PLY and anything else as
+/// OBJ.
+pub fn load_meshes(path: impl AsRef<Path>) -> Result<Vec<Mesh>, MeshError> {
+    let path = path.as_ref();
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+
+    match extension.as_deref() {
+        Some("gltf" | "glb") => Ok(load_gltf(path)?),
+        Some("stl") => Ok(vec![load_stl(path)?]),
+        Some("ply") => Ok(vec![load_ply(path)?]),
+        _ => Ok(load_objs(path)?),
+    }
+}
+
+/// Loads every mesh file once, handing out shared handles to its models.
+#[derive(Default)]
+pub struct MeshCache {
+    meshes: Mutex<HashMap<PathBuf, ObjHandle>>,
+}
+
+impl MeshCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the models of the mesh file at `path`, loading it if it isn't cached yet.
+    /// Different paths to the same file share the same handle.
+    pub fn load(&self, path: impl AsRef<Path>) -> Result<ObjHandle, MeshError> {
+        let path = path.as_ref();
+        let key = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        // The lock is held while loading, so a file is never loaded twice at the same time.
+        let mut meshes = self.meshes.lock().unwrap();
+
+        if let Some(objs) = meshes.get(&key) {
+            return Ok(Arc::clone(objs));
+        }
+
+        let objs = Arc::new(load_meshes(path)?);
+        meshes.insert(key, Arc::clone(&objs));
+
+        Ok(objs)
+    }
+
+    /// Loads all the given files ahead of time, so creating entities later doesn't stall.
+    pub fn preload<P: AsRef<Path>>(
+        &self,
+        paths: impl IntoIterator<Item = P>,
+    ) -> Result<(), MeshError> {
+        for path in paths {
+            self.load(path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks if the file at `path` was already loaded.
+    pub fn contains(&self, path: impl AsRef<Path>) -> bool {
+        let path = path.as_ref();
+        let key = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+
+        self.meshes.lock().unwrap().contains_key(&key)
+    }
+}
+
+/// The cache shared by the whole program, used by the planet presets and the scene loader.
+pub fn mesh_cache() -> &'static MeshCache {
+    static CACHE: OnceLock<MeshCache> = OnceLock::new();
+
+    CACHE.get_or_init(MeshCache::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::planets::{create_gas_giant, create_sun, planet_mesh};
+
+    #[test]
+    fn test_meshes_are_loaded_once() {
+        let cache = MeshCache::new();
+        assert!(!cache.contains("sphere.obj"));
+
+        let first = cache.load("sphere.obj").unwrap();
+        let second = cache.load("./sphere.obj").unwrap();
+
+        assert!(cache.contains("sphere.obj"));
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(!first.is_empty());
+    }
+
+    #[test]
+    fn test_presets_share_vertex_data() {
+        let sun = create_sun();
+        let gas_giant = create_gas_giant();
+
+        assert!(Arc::ptr_eq(&sun.objs, &gas_giant.objs));
+        assert!(Arc::ptr_eq(&sun.objs, &planet_mesh()));
+    }
+
+    #[test]
+    fn test_loader_follows_extension() {
+        assert!(matches!(
+            load_meshes("does_not_exist.GLB"),
+            Err(MeshError::Gltf(_))
+        ));
+        assert!(matches!(
+            load_meshes("does_not_exist.gltf"),
+            Err(MeshError::Gltf(_))
+        ));
+        assert!(matches!(
+            load_meshes("does_not_exist.stl"),
+            Err(MeshError::Stl(_))
+        ));
+        assert!(matches!(
+            load_meshes("does_not_exist.ply"),
+            Err(MeshError::Ply(_))
+        ));
+        assert!(matches!(
+            load_meshes("does_not_exist.obj"),
+            Err(MeshError::Obj(_))
+        ));
+    }
+
+    #[test]
+    fn test_preload() {
+        let cache = MeshCache::new();
+
+        cache.preload(["sphere.obj", "cube.obj"]).unwrap();
+
+        assert!(cache.contains("sphere.obj"));
+        assert!(cache.contains("cube.obj"));
+        assert!(cache.preload(["does_not_exist.obj"]).is_err());
+        assert!(!cache.contains("does_not_exist.obj"));
+    }
+}
@@ -1,6 +1,7 @@
 use std::{
-    fs::File,
-    io::{BufWriter, Write},
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
 };
 
 use crate::color::Color;
@@ -34,6 +35,67 @@ pub fn write_bmp_file(
     writer.flush()
 }
 
+/// Reads a 24 or 32 bits uncompressed BMP file, with its rows either bottom-up or top-down.
+///
+/// Returns the width and height of the image, and its pixels in the same layout as
+/// `Framebuffer::buffer`: row by row from the top left corner. The alpha channel of 32 bits
+/// images is ignored, and images without pixels are invalid.
+pub fn read_bmp_file(file_path: impl AsRef<Path>) -> io::Result<(usize, usize, Vec<u32>)> {
+    let data = fs::read(file_path)?;
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let read_u32 = |offset: usize| {
+        data.get(offset..offset + 4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+            .ok_or_else(|| invalid("The BMP header is incomplete"))
+    };
+
+    if !data.starts_with(b"BM") {
+        return Err(invalid("The file isn't a BMP"));
+    }
+    let pixel_offset = read_u32(10)? as usize;
+    let width = read_u32(18)? as i32;
+    // A negative height means the rows are stored from the top down.
+    let height = read_u32(22)? as i32;
+    let bits_per_pixel = (read_u32(28)? & 0xFFFF) as usize;
+    let compression = read_u32(30)?;
+    if !matches!(bits_per_pixel, 24 | 32) || compression != 0 {
+        return Err(invalid(
+            "Only 24 and 32 bits uncompressed BMPs are supported",
+        ));
+    }
+    if width < 0 {
+        return Err(invalid("The BMP width can't be negative"));
+    }
+    let (width, top_down) = (width as usize, height < 0);
+    let height = height.unsigned_abs() as usize;
+    if width == 0 || height == 0 {
+        return Err(invalid("The BMP is empty"));
+    }
+
+    // Rows are padded to 4 bytes, which 32 bits pixels always are.
+    let bytes_per_pixel = bits_per_pixel / 8;
+    let row_size = (width * bytes_per_pixel).div_ceil(4) * 4;
+    let pixels = data
+        .get(pixel_offset..pixel_offset + row_size * height)
+        .ok_or_else(|| invalid("The BMP pixel data is incomplete"))?;
+
+    let rows = pixels.chunks(row_size);
+    let rows: Box<dyn Iterator<Item = &[u8]>> = if top_down {
+        Box::new(rows)
+    } else {
+        Box::new(rows.rev())
+    };
+    let buffer = rows
+        .flat_map(|row| {
+            row[..width * bytes_per_pixel]
+                .chunks(bytes_per_pixel)
+                .map(|bgr| u32::from(Color::new(bgr[2], bgr[1], bgr[0])))
+        })
+        .collect();
+
+    Ok((width, height, buffer))
+}
+
 /// Writes a .bmp header into the given `writer`.
 fn generate_header(width: usize, height: usize, data_byte_length: usize) -> Vec<u8> {
     let byte_file_size = BMP_HEADER_SIZE as u32 + data_byte_length as u32;
@@ -85,7 +147,7 @@ fn pad_buffer(buffer: &[u32], width: usize) -> Vec<u8> {
             // Microsoft do be smoking...
             let vec = vec![b, g, r];
 
-            if (i + 1) % width == 0 && i != 0 {
+            if (i + 1) % width == 0 {
                 vec.into_iter().chain(padding_per_row.clone()).collect()
             } else {
                 vec
@@ -111,3 +173,101 @@ fn compute_padding_bytes_per_row(width: usize) -> usize {
     ((4.0 * (color_bytes_per_row as f32 / 4.0).ceil()) - color_bytes_per_row as f32).floor()
         as usize
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::temp_path;
+
+    /// A BMP header for the given pixel data, like `generate_header` but for any pixel size and
+    /// possibly negative heights.
+    fn header(width: i32, height: i32, bits_per_pixel: u16, data: &[u8]) -> Vec<u8> {
+        let mut header = b"BM".to_vec();
+        header.extend((BMP_HEADER_SIZE as u32 + data.len() as u32).to_le_bytes());
+        header.extend([0; 4]);
+        header.extend((BMP_PIXEL_OFFSET as u32).to_le_bytes());
+        header.extend((DIB_HEADER_SIZE as u32).to_le_bytes());
+        header.extend(width.to_le_bytes());
+        header.extend(height.to_le_bytes());
+        header.extend(1u16.to_le_bytes());
+        header.extend(bits_per_pixel.to_le_bytes());
+        header.extend([0; 4]);
+        header.extend((data.len() as u32).to_le_bytes());
+        header.extend([0; 16]);
+        header.extend(data);
+        header
+    }
+
+    #[test]
+    fn test_round_trip_keeps_padding() {
+        // Every width needs a different amount of padding.
+        for width in 1..=5 {
+            let height = 3;
+            let buffer: Vec<u32> = (0..width * height)
+                .map(|i| 0x010203 * i as u32 + 0x400000)
+                .collect();
+            let path = temp_path(&format!("round_trip_{width}.bmp"));
+
+            write_bmp_file(path.to_str().unwrap(), &buffer, width, height).unwrap();
+            let image = read_bmp_file(&path);
+            fs::remove_file(path).unwrap();
+
+            assert_eq!(image.unwrap(), (width, height, buffer));
+        }
+    }
+
+    #[test]
+    fn test_32_bits_top_down() {
+        // 2x2 BGRA pixels with the top row first, the alpha channel is ignored.
+        let data = [
+            [0, 0, 255, 0],
+            [0, 255, 0, 128],
+            [255, 0, 0, 255],
+            [255, 255, 255, 255],
+        ]
+        .concat();
+        let path = temp_path("top_down.bmp");
+        fs::write(&path, header(2, -2, 32, &data)).unwrap();
+
+        let image = read_bmp_file(&path);
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            image.unwrap(),
+            (2, 2, vec![0xff0000, 0x00ff00, 0x0000ff, 0xffffff])
+        );
+    }
+
+    #[test]
+    fn test_24_bits_top_down() {
+        // A single row of 3 pixels needs 3 bytes of padding.
+        let data = [0, 0, 255, 0, 255, 0, 255, 0, 0, 0, 0, 0];
+        let path = temp_path("top_down_24.bmp");
+        fs::write(&path, header(3, -1, 24, &data)).unwrap();
+
+        let image = read_bmp_file(&path);
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(image.unwrap(), (3, 1, vec![0xff0000, 0x00ff00, 0x0000ff]));
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        let cases = [
+            ("not_bmp", b"PNG but not really".to_vec()),
+            ("paletted", header(1, 1, 8, &[0, 0, 0, 0])),
+            ("truncated", header(2, 2, 24, &[0; 8])),
+            ("no_width", header(0, 2, 24, &[])),
+            ("no_height", header(2, 0, 32, &[])),
+        ];
+
+        for (name, data) in cases {
+            let path = temp_path(&format!("{name}.bmp"));
+            fs::write(&path, data).unwrap();
+            let error = read_bmp_file(&path).unwrap_err();
+            fs::remove_file(path).unwrap();
+
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{name}");
+        }
+    }
+}
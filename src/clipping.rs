@@ -0,0 +1,161 @@
+use nalgebra_glm::{dot, Mat4, Vec4};
+
+use crate::vertex::Vertex;
+
+/// A plane in clip space, a vertex is inside the plane when `dot(plane, clip_position) >= 0`.
+pub type ClipPlane = Vec4;
+
+/// Keeps everything in front of the near plane (`z >= -w`).
+pub const NEAR_PLANE: ClipPlane = Vec4::new(0.0, 0.0, 1.0, 1.0);
+/// Keeps everything behind the far plane (`z <= w`).
+pub const FAR_PLANE: ClipPlane = Vec4::new(0.0, 0.0, -1.0, 1.0);
+pub const LEFT_PLANE: ClipPlane = Vec4::new(1.0, 0.0, 0.0, 1.0);
+pub const RIGHT_PLANE: ClipPlane = Vec4::new(-1.0, 0.0, 0.0, 1.0);
+pub const BOTTOM_PLANE: ClipPlane = Vec4::new(0.0, 1.0, 0.0, 1.0);
+pub const TOP_PLANE: ClipPlane = Vec4::new(0.0, -1.0, 0.0, 1.0);
+
+/// All the planes of the view frustum.
+pub const FRUSTUM_PLANES: [ClipPlane; 6] = [
+    NEAR_PLANE,
+    FAR_PLANE,
+    LEFT_PLANE,
+    RIGHT_PLANE,
+    BOTTOM_PLANE,
+    TOP_PLANE,
+];
+
+/// Clips a triangle against the given planes using Sutherland–Hodgman.
+///
+/// Returns `None` when the triangle is completely inside every plane, so it can be used as is.
+/// Otherwise the clipped polygon is triangulated as a fan and every resulting vertex is projected
+/// into screen space with the `viewport_matrix`. Triangles completely outside yield an empty list.
+///
+/// * `triangle`: The three vertices of the triangle, as returned by the vertex shader.
+/// * `planes`: The planes to clip against.
+/// * `viewport_matrix`: The matrix that maps from NDC into the framebuffer.
+pub fn clip_triangle(
+    triangle: [&Vertex; 3],
+    planes: &[ClipPlane],
+    viewport_matrix: &Mat4,
+) -> Option<Vec<Vertex>> {
+    let is_inside = planes
+        .iter()
+        .all(|plane| triangle.iter().all(|v| dot(plane, &v.clip_position) >= 0.0));
+    if is_inside {
+        return None;
+    }
+
+    let polygon = planes
+        .iter()
+        .fold(triangle.map(Vertex::clone).to_vec(), |polygon, plane| {
+            clip_polygon(&polygon, plane)
+        });
+
+    if polygon.len() < 3 {
+        return Some(vec![]);
+    }
+
+    let polygon: Vec<Vertex> = polygon
+        .into_iter()
+        .map(|v| v.project(viewport_matrix))
+        .collect();
+
+    let triangles = (1..polygon.len() - 1)
+        .flat_map(|i| [&polygon[0], &polygon[i], &polygon[i + 1]])
+        .cloned()
+        .collect();
+
+    Some(triangles)
+}
+
+/// Clips a convex polygon against a single plane, interpolating the attributes of the vertices
+/// created where the edges cross the plane.
+fn clip_polygon(polygon: &[Vertex], plane: &ClipPlane) -> Vec<Vertex> {
+    let mut clipped = Vec::with_capacity(polygon.len() + 1);
+
+    for (i, current) in polygon.iter().enumerate() {
+        let next = &polygon[(i + 1) % polygon.len()];
+        let current_distance = dot(plane, &current.clip_position);
+        let next_distance = dot(plane, &next.clip_position);
+
+        if current_distance >= 0.0 {
+            clipped.push(current.clone());
+        }
+
+        if (current_distance >= 0.0) != (next_distance >= 0.0) {
+            let t = current_distance / (current_distance - next_distance);
+            clipped.push(current.lerp(next, t));
+        }
+    }
+
+    clipped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shader::{create_projection_matrix, create_viewport_matrix};
+    use nalgebra_glm::{vec2, vec3, vec4, Vec2, Vec3};
+
+    fn clip_vertex(projection: &Mat4, position: Vec3, tex_coords: Vec2) -> Vertex {
+        Vertex {
+            clip_position: projection * vec4(position.x, position.y, position.z, 1.0),
+            ..Vertex::new(position, vec3(0.0, 0.0, 1.0), tex_coords)
+        }
+    }
+
+    #[test]
+    fn test_triangle_inside_is_not_clipped() {
+        let projection = create_projection_matrix(100.0, 100.0);
+        let viewport = create_viewport_matrix(100.0, 100.0);
+        let triangle = [
+            clip_vertex(&projection, vec3(-1.0, -1.0, -5.0), vec2(0.0, 0.0)),
+            clip_vertex(&projection, vec3(1.0, -1.0, -5.0), vec2(1.0, 0.0)),
+            clip_vertex(&projection, vec3(0.0, 1.0, -5.0), vec2(0.5, 1.0)),
+        ];
+
+        assert!(clip_triangle(triangle.each_ref(), &FRUSTUM_PLANES, &viewport).is_none());
+    }
+
+    #[test]
+    fn test_triangle_behind_camera_is_discarded() {
+        let projection = create_projection_matrix(100.0, 100.0);
+        let viewport = create_viewport_matrix(100.0, 100.0);
+        let triangle = [
+            clip_vertex(&projection, vec3(-1.0, -1.0, 5.0), vec2(0.0, 0.0)),
+            clip_vertex(&projection, vec3(1.0, -1.0, 5.0), vec2(1.0, 0.0)),
+            clip_vertex(&projection, vec3(0.0, 1.0, 5.0), vec2(0.5, 1.0)),
+        ];
+
+        let clipped = clip_triangle(triangle.each_ref(), &[NEAR_PLANE], &viewport).unwrap();
+
+        assert!(clipped.is_empty());
+    }
+
+    #[test]
+    fn test_triangle_crossing_near_plane() {
+        let projection = create_projection_matrix(100.0, 100.0);
+        let viewport = create_viewport_matrix(100.0, 100.0);
+        // One vertex behind the eye, the other two in front of it.
+        let triangle = [
+            clip_vertex(&projection, vec3(0.0, 0.0, 2.0), vec2(0.0, 0.0)),
+            clip_vertex(&projection, vec3(-1.0, 0.0, -2.0), vec2(1.0, 0.0)),
+            clip_vertex(&projection, vec3(1.0, 0.0, -2.0), vec2(1.0, 1.0)),
+        ];
+
+        let clipped = clip_triangle(triangle.each_ref(), &[NEAR_PLANE], &viewport).unwrap();
+
+        // A quad, split in two triangles.
+        assert_eq!(clipped.len(), 6);
+        for vertex in &clipped {
+            let clip = vertex.clip_position;
+            assert!(clip.z + clip.w >= -1e-5);
+            assert!(clip.w > 0.0);
+            assert!((vertex.inv_w - 1.0 / clip.w).abs() < 1e-5);
+            // The new vertices lie on the near plane, where the tex coords are interpolated.
+            if (clip.z + clip.w).abs() < 1e-5 {
+                assert!(vertex.tex_coords.x > 0.0 && vertex.tex_coords.x < 1.0);
+            }
+        }
+    }
+}
@@ -1,12 +1,36 @@
-use crate::{color::Color, shader::Uniforms, vertex::Vertex};
+use crate::{color::Color, material::Material, vertex::Vertex};
 use nalgebra_glm::{dot, vec3_to_vec2, Vec2, Vec3};
 
+/// The coordinate space a shader can sample positions in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Space {
+    /// The space the model was authored in, it doesn't change when the entity moves.
+    Object,
+    /// The space of the scene, after applying the model matrix.
+    World,
+}
+
+#[derive(Debug, Clone)]
 pub struct Fragment {
     pub position: Vec2,
     pub color: Color,
-    pub intensity: f32,
     pub depth: f32,
+    /// The perspective correct interpolated position of the fragment in object space.
     pub vertex_position: Vec3,
+    /// The perspective correct interpolated position of the fragment in world space.
+    pub world_position: Vec3,
+    /// The perspective correct interpolated normal of the fragment.
+    pub normal: Vec3,
+    /// The perspective correct interpolated texture coordinates of the fragment.
+    pub tex_coords: Vec2,
+    /// How much `tex_coords` changes from this pixel to the next one on the right.
+    pub tex_coords_dx: Vec2,
+    /// How much `tex_coords` changes from this pixel to the next one below.
+    pub tex_coords_dy: Vec2,
+    /// The perspective correct interpolated color of the vertices.
+    pub vertex_color: Color,
+    /// The material of the mesh the fragment belongs to, if it has one.
+    pub material: Option<Material>,
 }
 
 impl Fragment {
@@ -16,23 +40,21 @@ impl Fragment {
             color,
             depth,
             vertex_position,
-            intensity: 1.0,
+            world_position: vertex_position,
+            normal: Vec3::zeros(),
+            tex_coords: Vec2::zeros(),
+            tex_coords_dx: Vec2::zeros(),
+            tex_coords_dy: Vec2::zeros(),
+            vertex_color: Color::black(),
+            material: None,
         }
     }
 
-    pub fn new_with_intensity(
-        position: Vec2,
-        color: Color,
-        depth: f32,
-        vertex_position: Vec3,
-        intensity: f32,
-    ) -> Self {
-        Fragment {
-            position,
-            color,
-            intensity,
-            depth,
-            vertex_position,
+    /// Gets the position of the fragment in the given `space`.
+    pub fn position_in(&self, space: Space) -> Vec3 {
+        match space {
+            Space::Object => self.vertex_position,
+            Space::World => self.world_position,
         }
     }
 
@@ -80,70 +102,321 @@ pub fn wireframe_triangle(v1: &Vertex, v2: &Vertex, v3: &Vertex) -> Vec<Fragment
         .collect()
 }
 
-pub fn triangle(v1: &Vertex, v2: &Vertex, v3: &Vertex, camera_direction: &Vec3) -> Vec<Fragment> {
+/// Number of sub-pixel bits used when snapping vertices to the fixed point grid.
+const SUBPIXEL_BITS: u32 = 8;
+const SUBPIXEL_ONE: i64 = 1 << SUBPIXEL_BITS;
+const HALF_PIXEL: i64 = SUBPIXEL_ONE / 2;
+
+/// Snaps a screen space coordinate into the sub-pixel fixed point grid.
+fn to_fixed(value: f32) -> i64 {
+    (value * SUBPIXEL_ONE as f32).round() as i64
+}
+
+/// An edge of a triangle prepared for incremental evaluation.
+///
+/// The edge function is evaluated at the center of the first pixel inside the bounding box and
+/// then updated by adding `step_x` or `step_y` every time we move one pixel.
+struct Edge {
+    value: i64,
+    step_x: i64,
+    step_y: i64,
+    /// Subtracted from the edge value to apply the top-left fill rule.
+    /// Pixels exactly on top or left edges are considered inside, every other edge excludes them.
+    bias: i64,
+}
+
+impl Edge {
+    fn new(a: (i64, i64), b: (i64, i64), origin: (i64, i64)) -> Self {
+        let dx = b.0 - a.0;
+        let dy = b.1 - a.1;
+
+        // The triangle is always wound so its area is positive, with this winding
+        // (and screen Y pointing down) top edges go left and left edges go down.
+        let is_top = dy == 0 && dx < 0;
+        let is_left = dy > 0;
+
+        Edge {
+            value: (origin.0 - a.0) * dy - (origin.1 - a.1) * dx,
+            step_x: dy * SUBPIXEL_ONE,
+            step_y: -dx * SUBPIXEL_ONE,
+            bias: if is_top || is_left { 0 } else { 1 },
+        }
+    }
+
+    fn is_inside(&self, value: i64) -> bool {
+        value - self.bias >= 0
+    }
+}
+
+/// An inclusive rectangle of pixels the rasterizer is allowed to write to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelRect {
+    pub min_x: usize,
+    pub min_y: usize,
+    pub max_x: usize,
+    pub max_y: usize,
+}
+
+impl PixelRect {
+    pub fn new(min_x: usize, min_y: usize, max_x: usize, max_y: usize) -> Self {
+        PixelRect {
+            min_x,
+            min_y,
+            max_x,
+            max_y,
+        }
+    }
+
+    /// Creates the rectangle that covers a whole framebuffer of the given `width` and `height`.
+    /// Returns `None` if the framebuffer has no pixels.
+    pub fn from_dimensions(width: usize, height: usize) -> Option<Self> {
+        if width == 0 || height == 0 {
+            None
+        } else {
+            Some(PixelRect::new(0, 0, width - 1, height - 1))
+        }
+    }
+}
+
+/// Computes the pixels whose centers may be covered by the triangle, clamped to `bounds`.
+/// Returns `None` if the triangle doesn't touch any pixel center inside `bounds`.
+pub fn pixel_bounds(
+    v1: &Vertex,
+    v2: &Vertex,
+    v3: &Vertex,
+    bounds: &PixelRect,
+) -> Option<PixelRect> {
+    let (min, max) = calculate_bounding_box(&v1.position, &v2.position, &v3.position);
+    let min_x = (min.x - 0.5).ceil().max(bounds.min_x as f32);
+    let min_y = (min.y - 0.5).ceil().max(bounds.min_y as f32);
+    let max_x = (max.x - 0.5).floor().min(bounds.max_x as f32);
+    let max_y = (max.y - 0.5).floor().min(bounds.max_y as f32);
+
+    // Also rejects NaN coordinates.
+    if min_x <= max_x && min_y <= max_y {
+        Some(PixelRect::new(
+            min_x as usize,
+            min_y as usize,
+            max_x as usize,
+            max_y as usize,
+        ))
+    } else {
+        None
+    }
+}
+
+/// Rasterizes a triangle by sampling it at the center of every pixel inside its bounding box.
+///
+/// Vertices are snapped to a sub-pixel grid and the edge functions are evaluated incrementally
+/// using integers, with a top-left fill rule so triangles that share an edge never paint the same
+/// pixel twice nor leave gaps between them.
+/// The bounding box is clamped to the `width` and `height` of the framebuffer.
+pub fn triangle(
+    v1: &Vertex,
+    v2: &Vertex,
+    v3: &Vertex,
+    camera_direction: &Vec3,
+    width: usize,
+    height: usize,
+) -> Vec<Fragment> {
     // let mut fragments = wireframe_triangle(v1, v2, v3);
-    // let mut fragments = vec![];
+    let mut fragments = vec![];
 
-    let (a, b, c) = (v1.position, v2.position, v3.position);
+    if let Some(bounds) = PixelRect::from_dimensions(width, height) {
+        rasterize_triangle(v1, v2, v3, camera_direction, &bounds, |fragment| {
+            fragments.push(fragment)
+        });
+    }
+
+    fragments
+}
 
-    let triangle_area = edge_function(&a, &b, &vec3_to_vec2(&c));
-    let (min, max) = calculate_bounding_box(&a, &b, &c);
+/// Same as `triangle` but only the pixels inside `bounds` are sampled, and each fragment is
+/// handed to `emit` instead of being collected.
+///
+/// Since the edge functions are evaluated exactly, splitting a triangle across several `bounds`
+/// yields the same fragments as rasterizing it at once.
+pub fn rasterize_triangle<F>(
+    v1: &Vertex,
+    v2: &Vertex,
+    v3: &Vertex,
+    camera_direction: &Vec3,
+    bounds: &PixelRect,
+    mut emit: F,
+) where
+    F: FnMut(Fragment),
+{
+    let to_fixed_point = |v: &Vertex| (to_fixed(v.position.x), to_fixed(v.position.y));
+    let (mut v2, mut v3) = (v2, v3);
+    let (a, mut b, mut c) = (to_fixed_point(v1), to_fixed_point(v2), to_fixed_point(v3));
+
+    let mut triangle_area = (c.0 - a.0) * (b.1 - a.1) - (c.1 - a.1) * (b.0 - a.0);
+    if triangle_area == 0 {
+        return;
+    }
+    if triangle_area < 0 {
+        std::mem::swap(&mut v2, &mut v3);
+        std::mem::swap(&mut b, &mut c);
+        triangle_area = -triangle_area;
+    }
+    let triangle_area = triangle_area as f32;
+
+    let Some(PixelRect {
+        min_x,
+        min_y,
+        max_x,
+        max_y,
+    }) = pixel_bounds(v1, v2, v3, bounds)
+    else {
+        return;
+    };
+    let (min_x, min_y, max_x, max_y) = (min_x as i64, min_y as i64, max_x as i64, max_y as i64);
 
-    let light_dir = Vec3::new(0.0, 0.5, 1.0).normalize();
     let base_color = Color::new(100, 100, 100);
 
-    let step_size = 5e-1;
-    let y_step_count = ((max.y - min.y) / step_size).ceil() as u32;
-    let x_step_count = ((max.x - min.x) / step_size).ceil() as u32;
-
-    let fragments: Vec<Fragment> = (0..y_step_count)
-        .flat_map(|y_idx| {
-            let currenty = min.y + step_size * (y_idx as f32);
-
-            (0..x_step_count).filter_map(move |x_idx| {
-                let currentx = min.x + step_size * (x_idx as f32);
-
-                let point = Vec2::new(currentx, currenty);
-                let (w1, w2, w3) = barycentric_coordinates(&point, &a, &b, &c, triangle_area);
-
-                if (0.0..=1.0).contains(&w1)
-                    && (0.0..=1.0).contains(&w2)
-                    && (0.0..=1.0).contains(&w3)
-                {
-                    // Interpolated normal...
-                    let normal = w1 * v1.normal + w2 * v2.normal + w3 * v3.normal;
-                    let normal = normal.normalize();
-                    let camera_intensity = dot(&normal, camera_direction);
-                    if camera_intensity >= 0.0 {
-                        // If the camera is not looking at the fragment, don't compute it!
-                        return None;
-                    }
-
-                    let intensity = dot(&light_dir, &normal).clamp(0.0, 1.0);
-                    // if intensity <= 0.0 {
-                    //     println!("The intensity is {intensity}! {light_dir:?} dot {normal:?}");
-                    // }
-
-                    // Interpolated depth...
+    let origin = (
+        min_x * SUBPIXEL_ONE + HALF_PIXEL,
+        min_y * SUBPIXEL_ONE + HALF_PIXEL,
+    );
+    // Each edge is paired with the vertex opposite to it, that way its value is that vertex weight.
+    let mut e1 = Edge::new(b, c, origin);
+    let mut e2 = Edge::new(c, a, origin);
+    let mut e3 = Edge::new(a, b, origin);
+    // The barycentric weights are affine in screen space, so they change the same on every pixel.
+    let weight_dx = [e1.step_x, e2.step_x, e3.step_x].map(|step| step as f32 / triangle_area);
+    let weight_dy = [e1.step_y, e2.step_y, e3.step_y].map(|step| step as f32 / triangle_area);
+
+    let (a, b, c) = (v1.position, v2.position, v3.position);
+    for y in min_y..=max_y {
+        let (mut w1, mut w2, mut w3) = (e1.value, e2.value, e3.value);
+
+        for x in min_x..=max_x {
+            if e1.is_inside(w1) && e2.is_inside(w2) && e3.is_inside(w3) {
+                let (w1, w2, w3) = (
+                    w1 as f32 / triangle_area,
+                    w2 as f32 / triangle_area,
+                    w3 as f32 / triangle_area,
+                );
+                let (p1, p2, p3) = perspective_weights((w1, w2, w3), v1, v2, v3);
+
+                // Interpolated normal...
+                let normal = p1 * v1.normal + p2 * v2.normal + p3 * v3.normal;
+                let normal = normal.normalize();
+                let camera_intensity = dot(&normal, camera_direction);
+
+                // If the camera is not looking at the fragment, don't compute it!
+                if camera_intensity < 0.0 {
+                    // Depth is affine in screen space, so it's interpolated linearly...
                     let depth = w1 * a.z + w2 * b.z + w3 * c.z;
+                    let tex_coords = p1 * v1.tex_coords + p2 * v2.tex_coords + p3 * v3.tex_coords;
+                    let tex_coords_derivative = |weight_derivatives| {
+                        let (d1, d2, d3) = perspective_weights_derivative(
+                            (w1, w2, w3),
+                            weight_derivatives,
+                            v1,
+                            v2,
+                            v3,
+                        );
+                        d1 * v1.tex_coords + d2 * v2.tex_coords + d3 * v3.tex_coords
+                    };
+                    let vertex_color =
+                        interpolate_color((p1, p2, p3), &v1.color, &v2.color, &v3.color);
 
-                    // Interpolated position...
-                    // let position = a;
-                    // FIXME: For now the normal is fine, but this should ideally be
-                    // a position using barycentrics
-                    let position = normal;
-                    // let position = a * w1 + b * w2 + c * w3;
-                    Some(Fragment::new_with_intensity(
-                        point, base_color, depth, position, intensity,
-                    ))
-                } else {
-                    None
+                    // Interpolated positions...
+                    let object_position =
+                        p1 * v1.object_position + p2 * v2.object_position + p3 * v3.object_position;
+                    let world_position =
+                        p1 * v1.world_position + p2 * v2.world_position + p3 * v3.world_position;
+                    emit(Fragment {
+                        normal,
+                        tex_coords,
+                        tex_coords_dx: tex_coords_derivative(weight_dx),
+                        tex_coords_dy: tex_coords_derivative(weight_dy),
+                        vertex_color,
+                        world_position,
+                        ..Fragment::new(
+                            Vec2::new(x as f32, y as f32),
+                            base_color,
+                            depth,
+                            object_position,
+                        )
+                    });
                 }
-            })
-        })
-        .collect();
+            }
 
-    fragments
+            w1 += e1.step_x;
+            w2 += e2.step_x;
+            w3 += e3.step_x;
+        }
+
+        e1.value += e1.step_y;
+        e2.value += e2.step_y;
+        e3.value += e3.step_y;
+    }
+}
+
+/// Converts screen space barycentric coordinates into perspective correct ones.
+///
+/// Attributes are not affine in screen space after the perspective divide, but `attribute / w` is.
+/// So we interpolate each `1 / w` and weight every vertex by it, the resulting weights can be used
+/// to interpolate any attribute directly.
+pub fn perspective_weights(
+    (w1, w2, w3): (f32, f32, f32),
+    v1: &Vertex,
+    v2: &Vertex,
+    v3: &Vertex,
+) -> (f32, f32, f32) {
+    let (p1, p2, p3) = (w1 * v1.inv_w, w2 * v2.inv_w, w3 * v3.inv_w);
+    let inv_w = p1 + p2 + p3;
+
+    if inv_w == 0.0 {
+        (w1, w2, w3)
+    } else {
+        (p1 / inv_w, p2 / inv_w, p3 / inv_w)
+    }
+}
+
+/// Computes how the perspective correct weights of `perspective_weights` change when the screen
+/// space weights change by `derivatives`.
+///
+/// Each perspective weight is `w * inv_w / sum(w * inv_w)`, so by the quotient rule its derivative
+/// is `(dw * inv_w - p * sum(dw * inv_w)) / sum(w * inv_w)`.
+pub fn perspective_weights_derivative(
+    (w1, w2, w3): (f32, f32, f32),
+    [d1, d2, d3]: [f32; 3],
+    v1: &Vertex,
+    v2: &Vertex,
+    v3: &Vertex,
+) -> (f32, f32, f32) {
+    let inv_w = w1 * v1.inv_w + w2 * v2.inv_w + w3 * v3.inv_w;
+
+    if inv_w == 0.0 {
+        (d1, d2, d3)
+    } else {
+        let (p1, p2, p3) = perspective_weights((w1, w2, w3), v1, v2, v3);
+        let (q1, q2, q3) = (d1 * v1.inv_w, d2 * v2.inv_w, d3 * v3.inv_w);
+        let dq = q1 + q2 + q3;
+
+        (
+            (q1 - p1 * dq) / inv_w,
+            (q2 - p2 * dq) / inv_w,
+            (q3 - p3 * dq) / inv_w,
+        )
+    }
+}
+
+fn interpolate_color((w1, w2, w3): (f32, f32, f32), c1: &Color, c2: &Color, c3: &Color) -> Color {
+    let channel = |a: u8, b: u8, c: u8| {
+        (w1 * a as f32 + w2 * b as f32 + w3 * c as f32)
+            .round()
+            .clamp(0.0, 255.0) as u8
+    };
+
+    Color::new(
+        channel(c1.r, c2.r, c3.r),
+        channel(c1.g, c2.g, c3.g),
+        channel(c1.b, c2.b, c3.b),
+    )
 }
 
 pub fn calculate_bounding_box(v1: &Vec3, v2: &Vec3, v3: &Vec3) -> (Vec2, Vec2) {
@@ -156,25 +429,324 @@ pub fn calculate_bounding_box(v1: &Vec3, v2: &Vec3, v3: &Vec3) -> (Vec2, Vec2) {
     (Vec2::new(minx, miny), Vec2::new(maxx, maxy))
 }
 
-// pub fn barycentric_coordinates(p: &Vec3, a: &Vec3, b: &Vec3, c: &Vec3) -> (f32, f32, f32) {
-//     let pa = a - p;
-//     let ab = b - a;
-//     let ac = c - a;
-//
-//     let v = (pa.y * ab.x - pa.x * ab.y) / (ac.x * ab.y - ac.y * ab.x);
-//     let u = -(v * ac.y + pa.y) / ab.y;
-//     let w = 1.0 - u - v;
-//
-//     (u, v, w)
-// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shader::{
+        create_model_matrix, create_normal_matrix, create_projection_matrix, create_view_matrix,
+        create_viewport_matrix, vertex_shader, Uniforms,
+    };
+    use nalgebra_glm::{vec4, Mat4, Vec4};
 
-fn barycentric_coordinates(p: &Vec2, a: &Vec3, b: &Vec3, c: &Vec3, area: f32) -> (f32, f32, f32) {
-    let w1 = edge_function(b, c, p) / area;
-    let w2 = edge_function(c, a, p) / area;
-    let w3 = edge_function(a, b, p) / area;
+    const WIDTH: usize = 32;
+    const HEIGHT: usize = 32;
 
-    (w1, w2, w3)
-}
-fn edge_function(a: &Vec3, b: &Vec3, c: &Vec2) -> f32 {
-    (c.x - a.x) * (b.y - a.y) - (c.y - a.y) * (b.x - a.x)
+    fn vertex(x: f32, y: f32) -> Vertex {
+        Vertex::new(
+            Vec3::new(x, y, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec2::zeros(),
+        )
+    }
+
+    fn coverage(triangles: &[(Vertex, Vertex, Vertex)]) -> Vec<u32> {
+        let camera_direction = Vec3::new(0.0, 0.0, -1.0);
+        let mut coverage = vec![0; WIDTH * HEIGHT];
+
+        for (a, b, c) in triangles {
+            for fragment in triangle(a, b, c, &camera_direction, WIDTH, HEIGHT) {
+                let idx = fragment.position.y as usize * WIDTH + fragment.position.x as usize;
+                coverage[idx] += 1;
+            }
+        }
+
+        coverage
+    }
+
+    #[test]
+    fn test_samples_at_pixel_centers() {
+        let square = [
+            (vertex(2.0, 2.0), vertex(6.0, 2.0), vertex(6.0, 6.0)),
+            (vertex(2.0, 2.0), vertex(6.0, 6.0), vertex(2.0, 6.0)),
+        ];
+
+        let coverage = coverage(&square);
+
+        assert_eq!(coverage.iter().sum::<u32>(), 16);
+        for y in 2..6 {
+            for x in 2..6 {
+                assert_eq!(coverage[y * WIDTH + x], 1, "pixel ({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn test_shared_edges_are_watertight() {
+        // A fan of triangles with awkward, non pixel aligned vertices.
+        let center = vertex(15.3, 16.7);
+        let ring = [
+            vertex(3.1, 2.2),
+            vertex(17.9, 0.4),
+            vertex(30.2, 9.6),
+            vertex(28.7, 29.3),
+            vertex(12.4, 31.1),
+            vertex(1.3, 20.8),
+        ];
+        let fan: Vec<_> = (0..ring.len())
+            .map(|i| {
+                (
+                    center.clone(),
+                    ring[i].clone(),
+                    ring[(i + 1) % ring.len()].clone(),
+                )
+            })
+            .collect();
+
+        let coverage = coverage(&fan);
+
+        assert!(coverage.iter().all(|&count| count <= 1));
+        // Pixels around the center must be covered by exactly one triangle.
+        for y in 10..22 {
+            for x in 10..22 {
+                assert_eq!(coverage[y * WIDTH + x], 1, "pixel ({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn test_winding_does_not_matter() {
+        let clockwise = coverage(&[(vertex(1.0, 1.0), vertex(9.0, 1.0), vertex(1.0, 9.0))]);
+        let counter_clockwise = coverage(&[(vertex(1.0, 1.0), vertex(1.0, 9.0), vertex(9.0, 1.0))]);
+
+        assert_eq!(clockwise, counter_clockwise);
+    }
+
+    /// Builds a quad that recedes into the screen, the top is further from the camera than the
+    /// bottom. Its texture coordinates map (x, y) from [-1, 1] into [0, 1].
+    fn tilted_quad() -> Vec<Vertex> {
+        let corner = |x: f32, y: f32| {
+            let z = -2.0 * (y + 1.0);
+            let normal = Vec3::new(0.0, 2.0, 1.0).normalize();
+            let tex_coords = Vec2::new((x + 1.0) / 2.0, (y + 1.0) / 2.0);
+            Vertex::new(Vec3::new(x, y, z), normal, tex_coords)
+        };
+        let (a, b, c, d) = (
+            corner(-1.0, -1.0),
+            corner(1.0, -1.0),
+            corner(1.0, 1.0),
+            corner(-1.0, 1.0),
+        );
+
+        vec![a.clone(), b, c.clone(), a, c, d]
+    }
+
+    fn tilted_quad_uniforms() -> Uniforms {
+        Uniforms {
+            view_matrix: create_view_matrix(
+                Vec3::new(0.0, 0.0, 3.0),
+                Vec3::zeros(),
+                Vec3::new(0.0, 1.0, 0.0),
+            ),
+            projection_matrix: create_projection_matrix(WIDTH as f32, HEIGHT as f32),
+            viewport_matrix: create_viewport_matrix(WIDTH as f32, HEIGHT as f32),
+            time: 0.0,
+            camera_position: Vec3::new(0.0, 0.0, 3.0),
+            lights: vec![],
+            ambient_intensity: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_perspective_correct_tex_coords() {
+        let uniforms = tilted_quad_uniforms();
+        let model_matrix = Mat4::identity();
+        let normal_matrix = create_normal_matrix(&model_matrix);
+        let camera_direction = Vec3::new(0.0, 0.0, -1.0);
+        let screen_to_world =
+            (uniforms.viewport_matrix * uniforms.projection_matrix * uniforms.view_matrix)
+                .try_inverse()
+                .unwrap();
+
+        let vertices: Vec<Vertex> = tilted_quad()
+            .iter()
+            .map(|v| vertex_shader(v, &uniforms, &model_matrix, &normal_matrix))
+            .collect();
+        // The same triangles without 1/w end up being interpolated in screen space.
+        let affine_vertices: Vec<Vertex> = vertices
+            .iter()
+            .map(|v| Vertex {
+                inv_w: 1.0,
+                ..v.clone()
+            })
+            .collect();
+        let rasterize = |vertices: &[Vertex]| -> Vec<Fragment> {
+            vertices
+                .chunks(3)
+                .flat_map(|tri| {
+                    triangle(&tri[0], &tri[1], &tri[2], &camera_direction, WIDTH, HEIGHT)
+                })
+                .collect()
+        };
+        let fragments = rasterize(&vertices);
+        let affine_fragments = rasterize(&affine_vertices);
+
+        assert!(fragments.len() > 50);
+        let mut max_affine_error: f32 = 0.0;
+        for (fragment, affine) in fragments.iter().zip(affine_fragments) {
+            // Unprojecting the sample point gives us the exact point on the quad.
+            let sample = vec4(
+                fragment.position.x + 0.5,
+                fragment.position.y + 0.5,
+                fragment.depth,
+                1.0,
+            );
+            let world = screen_to_world * sample;
+            let (x, y) = (world.x / world.w, world.y / world.w);
+            let expected = Vec2::new((x + 1.0) / 2.0, (y + 1.0) / 2.0);
+
+            assert!(
+                (fragment.tex_coords - expected).abs().max() < 1e-3,
+                "{:?} != {expected:?}",
+                fragment.tex_coords
+            );
+            max_affine_error = max_affine_error.max((affine.tex_coords - expected).abs().max());
+        }
+        assert!(max_affine_error > 1e-2);
+    }
+
+    #[test]
+    fn test_perspective_correct_vertex_color() {
+        let uniforms = tilted_quad_uniforms();
+        let model_matrix = Mat4::identity();
+        let normal_matrix = create_normal_matrix(&model_matrix);
+        let camera_direction = Vec3::new(0.0, 0.0, -1.0);
+
+        // The color ramps with the texture coordinates, so they must agree on every fragment.
+        let vertices: Vec<Vertex> = tilted_quad()
+            .into_iter()
+            .map(|v| {
+                let value = (v.tex_coords.y * 255.0) as u8;
+                let v = Vertex {
+                    color: Color::new(value, value, value),
+                    ..v
+                };
+                vertex_shader(&v, &uniforms, &model_matrix, &normal_matrix)
+            })
+            .collect();
+
+        for tri in vertices.chunks(3) {
+            for fragment in triangle(&tri[0], &tri[1], &tri[2], &camera_direction, WIDTH, HEIGHT) {
+                let expected = fragment.tex_coords.y * 255.0;
+                assert!((fragment.vertex_color.r as f32 - expected).abs() <= 1.0);
+                assert!((fragment.normal.magnitude() - 1.0).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn test_tex_coords_derivatives() {
+        let uniforms = tilted_quad_uniforms();
+        let model_matrix = Mat4::identity();
+        let normal_matrix = create_normal_matrix(&model_matrix);
+        let camera_direction = Vec3::new(0.0, 0.0, -1.0);
+        let screen_to_world =
+            (uniforms.viewport_matrix * uniforms.projection_matrix * uniforms.view_matrix)
+                .try_inverse()
+                .unwrap();
+        let plane_point = |a: Vec4, b: Vec4| {
+            // Intersects the ray through a screen point with the plane of the quad, z = -2y - 2.
+            let (a, b) = (a.xyz() / a.w, b.xyz() / b.w);
+            let direction = b - a;
+            let t = (-2.0 * a.y - 2.0 - a.z) / (direction.z + 2.0 * direction.y);
+            let point = a + t * direction;
+            Vec2::new((point.x + 1.0) / 2.0, (point.y + 1.0) / 2.0)
+        };
+        let tex_coords_at = |x: f32, y: f32| {
+            plane_point(
+                screen_to_world * vec4(x, y, 0.0, 1.0),
+                screen_to_world * vec4(x, y, 1.0, 1.0),
+            )
+        };
+
+        let vertices: Vec<Vertex> = tilted_quad()
+            .iter()
+            .map(|v| vertex_shader(v, &uniforms, &model_matrix, &normal_matrix))
+            .collect();
+
+        let mut fragments = 0;
+        for tri in vertices.chunks(3) {
+            for fragment in triangle(&tri[0], &tri[1], &tri[2], &camera_direction, WIDTH, HEIGHT) {
+                let (x, y) = (fragment.position.x + 0.5, fragment.position.y + 0.5);
+                // Central differences, a tiny step away from the pixel center.
+                let h = 1e-2;
+                let dx = (tex_coords_at(x + h, y) - tex_coords_at(x - h, y)) / (2.0 * h);
+                let dy = (tex_coords_at(x, y + h) - tex_coords_at(x, y - h)) / (2.0 * h);
+
+                assert!(
+                    (fragment.tex_coords_dx - dx).abs().max() < 1e-3,
+                    "{:?} != {dx:?}",
+                    fragment.tex_coords_dx
+                );
+                assert!(
+                    (fragment.tex_coords_dy - dy).abs().max() < 1e-3,
+                    "{:?} != {dy:?}",
+                    fragment.tex_coords_dy
+                );
+                fragments += 1;
+            }
+        }
+        assert!(fragments > 50);
+    }
+
+    #[test]
+    fn test_interpolated_positions() {
+        let uniforms = tilted_quad_uniforms();
+        let translation = Vec3::new(0.25, -0.5, 0.0);
+        let model_matrix = create_model_matrix(translation, 1.0, Vec3::zeros());
+        let normal_matrix = create_normal_matrix(&model_matrix);
+        let camera_direction = Vec3::new(0.0, 0.0, -1.0);
+        let screen_to_world =
+            (uniforms.viewport_matrix * uniforms.projection_matrix * uniforms.view_matrix)
+                .try_inverse()
+                .unwrap();
+
+        let vertices: Vec<Vertex> = tilted_quad()
+            .iter()
+            .map(|v| vertex_shader(v, &uniforms, &model_matrix, &normal_matrix))
+            .collect();
+
+        for tri in vertices.chunks(3) {
+            for fragment in triangle(&tri[0], &tri[1], &tri[2], &camera_direction, WIDTH, HEIGHT) {
+                let sample = vec4(
+                    fragment.position.x + 0.5,
+                    fragment.position.y + 0.5,
+                    fragment.depth,
+                    1.0,
+                );
+                let world = screen_to_world * sample;
+                let world = world.xyz() / world.w;
+
+                assert!((fragment.position_in(Space::World) - world).abs().max() < 1e-3);
+                assert!(
+                    (fragment.position_in(Space::Object) - (world - translation))
+                        .abs()
+                        .max()
+                        < 1e-3
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_bounding_box_is_clamped() {
+        let huge = [(
+            vertex(-1000.0, -1000.0),
+            vertex(3000.0, -1000.0),
+            vertex(-1000.0, 3000.0),
+        )];
+
+        let coverage = coverage(&huge);
+
+        assert!(coverage.iter().all(|&count| count == 1));
+    }
 }
@@ -0,0 +1,655 @@
+use std::{f32::consts::PI, sync::Arc};
+
+use fastnoise_lite::{CellularDistanceFunction, CellularReturnType, FastNoiseLite, FractalType};
+use nalgebra_glm::{dot, vec2, vec3, Vec3};
+
+use crate::{
+    clamp_with_universe,
+    color::Color,
+    fragment::{Fragment, Space},
+    material::Material,
+    shader::{create_noise, FragmentShader, Uniforms},
+    texture::{Filter, Texture, WrapMode},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct FractalConfig {
+    pub octaves: i32,
+    pub lacunarity: f32,
+    pub gain: f32,
+    pub weighted_strength: f32,
+    pub f_type: FractalType,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CellularConfig {
+    pub distance_func: CellularDistanceFunction,
+    pub return_type: CellularReturnType,
+    pub jitter: f32,
+}
+
+impl FractalConfig {
+    /// Configures the fractal settings of the given `noise` generator.
+    pub fn apply(&self, noise: &mut FastNoiseLite) {
+        let FractalConfig {
+            octaves,
+            lacunarity,
+            gain,
+            weighted_strength,
+            f_type,
+        } = *self;
+
+        noise.set_fractal_octaves(Some(octaves));
+        noise.set_fractal_gain(Some(gain));
+        noise.set_fractal_weighted_strength(Some(weighted_strength));
+        noise.set_fractal_type(Some(f_type));
+        noise.set_fractal_lacunarity(Some(lacunarity));
+    }
+}
+
+impl CellularConfig {
+    /// Configures the cellular settings of the given `noise` generator.
+    pub fn apply(&self, noise: &mut FastNoiseLite) {
+        let CellularConfig {
+            distance_func,
+            return_type,
+            jitter,
+        } = *self;
+
+        noise.set_cellular_distance_function(Some(distance_func));
+        noise.set_cellular_return_type(Some(return_type));
+        noise.set_cellular_jitter(Some(jitter));
+    }
+}
+
+/// Paints horizontal stripes cycling through the given `colors`.
+pub struct Stripe {
+    pub stripe_width: f32,
+    pub colors: Vec<Color>,
+}
+
+impl FragmentShader for Stripe {
+    fn shade(&self, fragment: &Fragment, _current_color: &Color, _uniforms: &Uniforms) -> Color {
+        let Stripe {
+            stripe_width,
+            colors,
+        } = self;
+        let y = fragment.vertex_position.y;
+        // let y = fragment.position.y as usize;
+
+        let stripe_idx = (y / stripe_width).abs() as usize % colors.len();
+        colors[stripe_idx]
+    }
+}
+
+/// Smooth stripes that move vertically through time, going from one color to the other.
+pub struct MovingStripes {
+    pub speed: f32,
+    pub stripe_width: f32,
+    pub colors: (Color, Color),
+}
+
+impl FragmentShader for MovingStripes {
+    fn shade(&self, fragment: &Fragment, _current_color: &Color, uniforms: &Uniforms) -> Color {
+        let MovingStripes {
+            speed,
+            stripe_width,
+            colors: (color1, color2),
+        } = self;
+
+        let moving_y = fragment.vertex_position.y + uniforms.time * speed;
+
+        let stripe_factor = ((moving_y / stripe_width) * PI).sin() * 0.5 + 0.5;
+        color1.lerp(color2, stripe_factor)
+    }
+}
+
+/// Glowing stripes, the `red` and `blue` factors tint the glow.
+pub struct GlowShader {
+    pub stripe_width: f32,
+    pub glow_size: f32,
+    pub red: f32,
+    pub blue: f32,
+}
+
+impl FragmentShader for GlowShader {
+    fn shade(&self, fragment: &Fragment, _current_color: &Color, _uniforms: &Uniforms) -> Color {
+        let GlowShader {
+            stripe_width,
+            glow_size,
+            red,
+            blue,
+        } = self;
+        let y = fragment.vertex_position.y;
+
+        let distance_to_center = (y % stripe_width - stripe_width / 2.0).abs();
+        let glow_intensity = ((1.0 - (distance_to_center / glow_size).min(1.0)) * PI / 2.0).sin();
+
+        Color::new(
+            (red * glow_intensity * 255.0) as u8,
+            (blue * glow_intensity * 255.0) as u8,
+            (glow_intensity * 255.0) as u8,
+        )
+    }
+}
+
+/// Lights the current color with the diffuse light that reaches the fragment, tinted by the color
+/// of each light.
+pub struct Intensity;
+
+impl FragmentShader for Intensity {
+    fn shade(&self, fragment: &Fragment, current_color: &Color, uniforms: &Uniforms) -> Color {
+        let Uniforms {
+            lights,
+            ambient_intensity,
+            ..
+        } = uniforms;
+        let normal = fragment.normal.normalize();
+
+        lights
+            .iter()
+            .fold(*current_color * *ambient_intensity, |acc, light| {
+                let light_dir = light.direction_from(&fragment.world_position);
+                let diffuse_intensity = dot(&normal, &light_dir).max(0.0) * light.intensity;
+
+                acc + current_color.blend_multiply(&light.color) * diffuse_intensity
+            })
+    }
+}
+
+/// Blinn-Phong lighting of the `material`, evaluated in world space for every light.
+///
+/// The diffuse color of the material is lit, so to light the colors of the previous shaders
+/// use a white material and the `Multiply` blend mode.
+pub struct BlinnPhong {
+    pub material: Material,
+}
+
+impl FragmentShader for BlinnPhong {
+    fn shade(&self, fragment: &Fragment, _current_color: &Color, uniforms: &Uniforms) -> Color {
+        let Uniforms {
+            camera_position,
+            lights,
+            ambient_intensity,
+            ..
+        } = uniforms;
+        let Material {
+            diffuse,
+            specular,
+            albedo: (diffuse_albedo, specular_albedo),
+            ..
+        } = &self.material;
+
+        let normal = fragment.normal.normalize();
+        let view_dir = (camera_position - fragment.world_position).normalize();
+
+        lights
+            .iter()
+            .fold(*diffuse * *ambient_intensity, |acc, light| {
+                let light_dir = light.direction_from(&fragment.world_position);
+                let diffuse_intensity = dot(&normal, &light_dir).max(0.0) * light.intensity;
+                if diffuse_intensity <= 0.0 {
+                    return acc;
+                }
+
+                let half_dir = (light_dir + view_dir).normalize();
+                let specular_intensity =
+                    dot(&normal, &half_dir).max(0.0).powf(*specular) * light.intensity;
+
+                acc + diffuse.blend_multiply(&light.color) * (diffuse_albedo * diffuse_intensity)
+                    + light.color * (specular_albedo * specular_intensity)
+            })
+    }
+}
+
+/// Paints every fragment with the same color.
+pub struct BaseColor {
+    pub color: Color,
+}
+
+impl FragmentShader for BaseColor {
+    fn shade(&self, _fragment: &Fragment, _current_color: &Color, _uniforms: &Uniforms) -> Color {
+        self.color
+    }
+}
+
+/// Paints every fragment with the diffuse color of its mesh material, as loaded from the MTL
+/// file. Fragments of meshes without a material keep the current color.
+pub struct MaterialDiffuse;
+
+impl FragmentShader for MaterialDiffuse {
+    fn shade(&self, fragment: &Fragment, current_color: &Color, _uniforms: &Uniforms) -> Color {
+        fragment
+            .material
+            .map_or(*current_color, |material| material.diffuse)
+    }
+}
+
+/// Paints the `texture` at the texture coordinates of the fragment, using their derivatives to
+/// filter what the pixel covers.
+pub struct TextureShader {
+    pub texture: Arc<Texture>,
+    pub filter: Filter,
+    pub wrap: WrapMode,
+}
+
+impl FragmentShader for TextureShader {
+    fn shade(&self, fragment: &Fragment, _current_color: &Color, _uniforms: &Uniforms) -> Color {
+        self.texture.sample_grad(
+            &fragment.tex_coords,
+            &fragment.tex_coords_dx,
+            &fragment.tex_coords_dy,
+            self.filter,
+            self.wrap,
+        )
+    }
+}
+
+/// Fractal OpenSimplex2 noise that moves horizontally through time.
+pub struct FBmShader {
+    pub zoom: f32,
+    pub speed: f32,
+    pub color: Color,
+    noise: FastNoiseLite,
+}
+
+impl FBmShader {
+    /// Creates the shader, configuring its noise generator with the given `fractal` settings.
+    pub fn new(zoom: f32, speed: f32, fractal: FractalConfig, color: Color) -> Self {
+        let mut noise = create_noise();
+        noise.set_noise_type(Some(fastnoise_lite::NoiseType::OpenSimplex2));
+        fractal.apply(&mut noise);
+
+        FBmShader {
+            zoom,
+            speed,
+            color,
+            noise,
+        }
+    }
+}
+
+impl FragmentShader for FBmShader {
+    fn shade(&self, fragment: &Fragment, _current_color: &Color, uniforms: &Uniforms) -> Color {
+        let Uniforms { time, .. } = uniforms;
+        let FBmShader {
+            zoom,
+            speed,
+            color,
+            noise,
+        } = self;
+
+        let x = fragment.vertex_position.x * zoom + speed * time;
+        let y = fragment.vertex_position.y * zoom;
+
+        let noise_value = noise.get_noise_2d(x, y);
+        let intensity = clamp_with_universe(vec2(-1.0, 1.0), vec2(0.0, 1.0), noise_value);
+
+        *color * intensity
+    }
+}
+
+/// Fractal cellular noise that moves horizontally through time.
+pub struct CellularShader {
+    pub zoom: f32,
+    pub speed: f32,
+    pub color: Color,
+    noise: FastNoiseLite,
+}
+
+impl CellularShader {
+    /// Creates the shader, configuring its noise generator with the given `fractal` and
+    /// `cellular` settings.
+    pub fn new(
+        zoom: f32,
+        speed: f32,
+        fractal: FractalConfig,
+        cellular: CellularConfig,
+        color: Color,
+    ) -> Self {
+        let mut noise = create_noise();
+        noise.set_noise_type(Some(fastnoise_lite::NoiseType::Cellular));
+        fractal.apply(&mut noise);
+        cellular.apply(&mut noise);
+
+        CellularShader {
+            zoom,
+            speed,
+            color,
+            noise,
+        }
+    }
+}
+
+impl FragmentShader for CellularShader {
+    fn shade(&self, fragment: &Fragment, _current_color: &Color, uniforms: &Uniforms) -> Color {
+        let Uniforms { time, .. } = uniforms;
+        let CellularShader {
+            zoom,
+            speed,
+            color,
+            noise,
+        } = self;
+
+        let x = fragment.vertex_position.x * zoom + speed * time;
+        let y = fragment.vertex_position.y * zoom;
+
+        let noise_value = noise.get_noise_2d(x, y);
+        let intensity = clamp_with_universe(vec2(-1.0, 1.0), vec2(0.0, 1.0), noise_value);
+
+        *color * intensity
+    }
+}
+
+/// Where a pattern shader reads the coordinates it is drawn with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatternCoordinates {
+    /// The texture coordinates of the mesh.
+    TexCoords,
+    /// The position of the fragment in the given space.
+    Position(Space),
+}
+
+impl PatternCoordinates {
+    fn sample(&self, fragment: &Fragment) -> Vec3 {
+        match self {
+            PatternCoordinates::TexCoords => {
+                vec3(fragment.tex_coords.x, fragment.tex_coords.y, 0.0)
+            }
+            PatternCoordinates::Position(space) => fragment.position_in(*space),
+        }
+    }
+}
+
+/// A checkerboard whose cells slide along the X axis and pulse through time.
+///
+/// Cells cycle through the given `colors`, each one pulsing with its own phase.
+pub struct AliveCheckerboard {
+    /// The size of each cell in the units of `coordinates`.
+    pub cell_size: f32,
+    /// How fast the cells slide along the X axis, in cells per millisecond.
+    pub speed: f32,
+    /// How fast the cells pulse, in radians per millisecond.
+    pub pulse_speed: f32,
+    /// The colors of the cells in turn, without colors the fragment is left as it is.
+    pub colors: Vec<Color>,
+    pub coordinates: PatternCoordinates,
+}
+
+impl FragmentShader for AliveCheckerboard {
+    fn shade(&self, fragment: &Fragment, current_color: &Color, uniforms: &Uniforms) -> Color {
+        let AliveCheckerboard {
+            cell_size,
+            speed,
+            pulse_speed,
+            colors,
+            coordinates,
+        } = self;
+        if colors.is_empty() {
+            return *current_color;
+        }
+
+        let cell = coordinates.sample(fragment) / *cell_size;
+        let cell = vec3(
+            (cell.x + uniforms.time * speed).floor(),
+            cell.y.floor(),
+            cell.z.floor(),
+        );
+
+        let cell_idx = (cell.x + cell.y + cell.z) as i64;
+        let color = colors[cell_idx.rem_euclid(colors.len() as i64) as usize];
+
+        // Every cell gets a pseudo random phase, so they don't pulse in unison.
+        let phase = (cell.x * 12.9898 + cell.y * 78.233 + cell.z * 37.719).sin() * 43758.547;
+        let pulse = (uniforms.time * pulse_speed + phase).sin() * 0.25 + 0.75;
+
+        color * pulse
+    }
+}
+
+/// Three interfering waves, each one tinting the fragment with its own color.
+/// The waves travel through time, making the pattern shimmer.
+pub struct Interference {
+    pub frequency: f32,
+    /// How fast the waves travel, in radians per millisecond.
+    pub speed: f32,
+    pub colors: (Color, Color, Color),
+    pub coordinates: PatternCoordinates,
+}
+
+impl FragmentShader for Interference {
+    fn shade(&self, fragment: &Fragment, _current_color: &Color, uniforms: &Uniforms) -> Color {
+        let Interference {
+            frequency,
+            speed,
+            colors: (color1, color2, color3),
+            coordinates,
+        } = self;
+
+        let position = coordinates.sample(fragment);
+        let (x, y) = (position.x, position.y);
+        let offset = uniforms.time * speed;
+
+        let wave1 = (x * 7.0 * frequency + y * 5.0 * frequency + offset).sin() * 0.5 + 0.5;
+        let wave2 =
+            (x * 5.0 * frequency - y * 8.0 * frequency + PI / 3.0 - offset).sin() * 0.5 + 0.5;
+        let wave3 =
+            (y * 6.0 * frequency + x * 4.0 * frequency + 2.0 * PI / 3.0 + offset * 0.5).sin() * 0.5
+                + 0.5;
+
+        // Where the waves agree the pattern is bright, where they cancel out it fades to black.
+        let total = wave1 + wave2 + wave3;
+        if total <= f32::EPSILON {
+            return Color::black();
+        }
+        let brightness = total / 3.0;
+
+        (*color1 * (wave1 / total) + *color2 * (wave2 / total) + *color3 * (wave3 / total))
+            * brightness
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::light::Light;
+    use nalgebra_glm::{Mat4, Vec2};
+
+    fn uniforms(time: f32) -> Uniforms {
+        Uniforms {
+            view_matrix: Mat4::identity(),
+            projection_matrix: Mat4::identity(),
+            viewport_matrix: Mat4::identity(),
+            time,
+            camera_position: Vec3::new(0.0, 0.0, 3.0),
+            lights: vec![Light::directional(
+                Vec3::new(0.0, 0.0, -1.0),
+                Color::white(),
+                1.0,
+            )],
+            ambient_intensity: 0.1,
+        }
+    }
+
+    fn fragment_at_uv(u: f32, v: f32) -> Fragment {
+        Fragment {
+            tex_coords: Vec2::new(u, v),
+            ..Fragment::new(Vec2::zeros(), Color::black(), 0.0, Vec3::zeros())
+        }
+    }
+
+    fn checkerboard(speed: f32) -> AliveCheckerboard {
+        AliveCheckerboard {
+            cell_size: 0.25,
+            speed,
+            pulse_speed: 0.0,
+            colors: vec![Color::new(200, 0, 0), Color::new(0, 0, 200)],
+            coordinates: PatternCoordinates::TexCoords,
+        }
+    }
+
+    fn is_red(color: Color) -> bool {
+        color.r > 0 && color.b == 0
+    }
+
+    #[test]
+    fn test_checkerboard_without_colors_keeps_the_color() {
+        let shader = AliveCheckerboard {
+            colors: vec![],
+            ..checkerboard(0.0)
+        };
+        let current = Color::new(10, 20, 30);
+
+        let color = shader.shade(&fragment_at_uv(0.35, 0.1), &current, &uniforms(0.0));
+
+        assert_eq!(color, current);
+    }
+
+    #[test]
+    fn test_checkerboard_alternates_cells() {
+        let shader = checkerboard(0.0);
+        let uniforms = uniforms(0.0);
+        let shade = |u, v| shader.shade(&fragment_at_uv(u, v), &Color::black(), &uniforms);
+
+        assert!(is_red(shade(0.1, 0.1)));
+        assert!(!is_red(shade(0.35, 0.1)));
+        assert!(!is_red(shade(0.1, 0.35)));
+        assert!(is_red(shade(0.35, 0.35)));
+    }
+
+    #[test]
+    fn test_checkerboard_moves_with_time() {
+        // One cell per millisecond.
+        let shader = checkerboard(1.0);
+        let fragment = fragment_at_uv(0.1, 0.1);
+        let shade = |time| shader.shade(&fragment, &Color::black(), &uniforms(time));
+
+        assert!(is_red(shade(0.0)));
+        assert!(!is_red(shade(1.0)));
+        assert!(is_red(shade(2.0)));
+    }
+
+    fn fragment_with_normal(normal: Vec3) -> Fragment {
+        Fragment {
+            normal,
+            ..Fragment::new(Vec2::zeros(), Color::black(), 0.0, Vec3::zeros())
+        }
+    }
+
+    #[test]
+    fn test_interference_shimmers_within_its_colors() {
+        let shader = Interference {
+            frequency: 2.0,
+            speed: 0.01,
+            colors: (
+                Color::new(100, 0, 0),
+                Color::new(0, 100, 0),
+                Color::new(0, 0, 100),
+            ),
+            coordinates: PatternCoordinates::TexCoords,
+        };
+        let shade =
+            |u, v, time| shader.shade(&fragment_at_uv(u, v), &Color::black(), &uniforms(time));
+
+        for (u, v) in [(0.1, 0.2), (0.5, 0.5), (0.9, 0.3)] {
+            let colors: Vec<Color> = (0..10).map(|i| shade(u, v, i as f32 * 100.0)).collect();
+
+            // The waves never add more of a color than the color itself.
+            assert!(colors
+                .iter()
+                .all(|c| c.r <= 100 && c.g <= 100 && c.b <= 100));
+            assert!(
+                colors.windows(2).any(|pair| pair[0] != pair[1]),
+                "{colors:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_intensity_follows_lights() {
+        let uniforms = uniforms(0.0);
+        let color = Color::new(200, 200, 200);
+        let shade = |normal| Intensity.shade(&fragment_with_normal(normal), &color, &uniforms);
+
+        // Facing the light gets the light and the ambient.
+        assert_eq!(shade(Vec3::new(0.0, 0.0, 1.0)), Color::new(220, 220, 220));
+        // Facing away only gets the ambient.
+        assert_eq!(shade(Vec3::new(0.0, 0.0, -1.0)), Color::new(20, 20, 20));
+
+        // Colored lights tint the color they light.
+        let red_light = Uniforms {
+            lights: vec![Light::directional(
+                Vec3::new(0.0, 0.0, -1.0),
+                Color::new(255, 0, 0),
+                1.0,
+            )],
+            ambient_intensity: 0.0,
+            ..uniforms
+        };
+        let lit = Intensity.shade(
+            &fragment_with_normal(Vec3::new(0.0, 0.0, 1.0)),
+            &color,
+            &red_light,
+        );
+        assert_eq!(lit, Color::new(200, 0, 0));
+    }
+
+    #[test]
+    fn test_material_diffuse() {
+        let uniforms = uniforms(0.0);
+        let current_color = Color::blue();
+        let mut fragment = fragment_at_uv(0.0, 0.0);
+
+        assert_eq!(
+            MaterialDiffuse.shade(&fragment, &current_color, &uniforms),
+            current_color
+        );
+
+        fragment.material = Some(Material {
+            diffuse: Color::new(204, 204, 204),
+            ..Material::default()
+        });
+        assert_eq!(
+            MaterialDiffuse.shade(&fragment, &current_color, &uniforms),
+            Color::new(204, 204, 204)
+        );
+    }
+
+    #[test]
+    fn test_texture_shader_samples_tex_coords() {
+        let uniforms = uniforms(0.0);
+        let shader = TextureShader {
+            texture: Arc::new(Texture::new(2, 1, &[0xff0000, 0x0000ff])),
+            filter: Filter::Nearest,
+            wrap: WrapMode::Repeat,
+        };
+        let shade = |u| shader.shade(&fragment_at_uv(u, 0.5), &Color::black(), &uniforms);
+
+        assert_eq!(shade(0.25), Color::red());
+        assert_eq!(shade(0.75), Color::blue());
+        assert_eq!(shade(1.25), Color::red());
+    }
+
+    #[test]
+    fn test_blinn_phong() {
+        let uniforms = uniforms(0.0);
+        let shader = BlinnPhong {
+            material: Material {
+                diffuse: Color::new(100, 0, 0),
+                specular: 50.0,
+                albedo: (1.0, 0.5),
+                ..Material::default()
+            },
+        };
+        let shade =
+            |normal| shader.shade(&fragment_with_normal(normal), &Color::black(), &uniforms);
+
+        // Light, camera and normal aligned: full diffuse and specular highlight.
+        assert_eq!(shade(Vec3::new(0.0, 0.0, 1.0)), Color::new(237, 127, 127));
+        // Tilted away from the highlight, there's barely any specular left.
+        let tilted = shade(Vec3::new(0.0, 0.5, 1.0).normalize());
+        assert!(tilted.r > 90 && tilted.r < 110 && tilted.g < 5);
+        // Facing away only gets the ambient.
+        assert_eq!(shade(Vec3::new(0.0, 0.0, -1.0)), Color::new(10, 0, 0));
+    }
+}
@@ -1,6 +1,12 @@
 use core::f32;
 
-use crate::{bmp::write_bmp_file, color::Color};
+use rayon::prelude::*;
+
+use crate::{
+    bmp::{read_bmp_file, write_bmp_file},
+    color::Color,
+    fragment::PixelRect,
+};
 
 type Buffer = Vec<u32>;
 
@@ -46,6 +52,47 @@ pub enum GetColorErrors {
     YTooLarge,
 }
 
+/// A horizontal band of rows of a `Framebuffer`.
+///
+/// Each tile owns its slice of the color and z buffers, so different tiles can be painted at the
+/// same time from different threads.
+pub struct Tile<'a> {
+    /// The first row of the framebuffer covered by the tile.
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    buffer: &'a mut [u32],
+    z_buffer: &'a mut [f32],
+}
+
+impl Tile<'_> {
+    /// The pixels of the framebuffer covered by the tile.
+    pub fn rect(&self) -> PixelRect {
+        PixelRect::new(0, self.y, self.width - 1, self.y + self.height - 1)
+    }
+
+    fn index(&self, x: usize, y: usize) -> usize {
+        (y - self.y) * self.width + x
+    }
+
+    /// Checks if a fragment with the given `depth` would be visible at (x, y).
+    /// Coordinates are in framebuffer space and must be inside `rect`.
+    pub fn is_visible(&self, x: usize, y: usize, depth: f32) -> bool {
+        self.z_buffer[self.index(x, y)] < depth
+    }
+
+    /// Colors the point at (x, y) if it passes the depth test.
+    /// Coordinates are in framebuffer space and must be inside `rect`.
+    pub fn paint(&mut self, x: usize, y: usize, color: Color, depth: f32) {
+        let idx = self.index(x, y);
+
+        if self.z_buffer[idx] < depth {
+            self.z_buffer[idx] = depth;
+            self.buffer[idx] = color.into();
+        }
+    }
+}
+
 impl Framebuffer {
     pub fn new(width: usize, height: usize) -> Self {
         let background_color = Color::default();
@@ -81,6 +128,27 @@ impl Framebuffer {
         self.empty_buffer.clone_from(&self.buffer)
     }
 
+    /// Loads a BMP, like the ones written by `save`, and saves it as a background.
+    ///
+    /// The image must have the same size as the framebuffer. The current buffer is replaced with
+    /// the image, as if it was painted and then saved with `save_as_background`.
+    pub fn load_background(&mut self, file_path: &str) -> std::io::Result<()> {
+        let (width, height, buffer) = read_bmp_file(file_path)?;
+        if (width, height) != (self.width, self.height) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "The background is {width}x{height} but the framebuffer is {}x{}",
+                    self.width, self.height
+                ),
+            ));
+        }
+
+        self.buffer = buffer;
+        self.save_as_background();
+        Ok(())
+    }
+
     /// Colors a point in the given location. Rounds x and y.
     /// If either x or y are exactly half between integers then the value is rounded up.
     ///
@@ -128,6 +196,39 @@ impl Framebuffer {
         }
     }
 
+    /// Splits the framebuffer into tiles of `tile_height` rows (the last one may be shorter),
+    /// that can be painted in parallel. Framebuffers without pixels have no tiles.
+    ///
+    /// Panics if `tile_height` is 0.
+    pub fn tiles_mut(
+        &mut self,
+        tile_height: usize,
+    ) -> impl IndexedParallelIterator<Item = Tile<'_>> {
+        let Framebuffer {
+            width,
+            height,
+            buffer,
+            z_buffer,
+            ..
+        } = self;
+        assert!(tile_height > 0, "A tile needs at least one row");
+        let (width, height) = (*width, *height);
+        // Empty framebuffers have no chunks, the size only has to be valid.
+        let chunk_size = (width * tile_height).max(1);
+
+        buffer
+            .par_chunks_mut(chunk_size)
+            .zip(z_buffer.par_chunks_mut(chunk_size))
+            .enumerate()
+            .map(move |(i, (buffer, z_buffer))| Tile {
+                y: i * tile_height,
+                width,
+                height: tile_height.min(height - i * tile_height),
+                buffer,
+                z_buffer,
+            })
+    }
+
     /// Gets the color of a point in the buffer.
     pub fn get_color(&self, x: usize, y: usize) -> Result<Color, GetColorErrors> {
         let Framebuffer {
@@ -180,3 +281,81 @@ impl Framebuffer {
         write_bmp_file(file_path, buffer, *width, *height)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render::render, scene_file::load_scene, test_utils::temp_path};
+
+    #[test]
+    fn test_saved_frame_matches_rendered_frame() {
+        let (width, height) = (61, 40);
+        let mut framebuffer = Framebuffer::new(width, height);
+        framebuffer.set_background_color(0x101020);
+        framebuffer.clear();
+        let model = load_scene("scenes/solar_system.toml", (width, height)).unwrap();
+        render(&mut framebuffer, &model);
+        let path = temp_path("rendered_frame.bmp");
+        let path = path.to_str().unwrap();
+
+        framebuffer.save(path).unwrap();
+        let image = read_bmp_file(path);
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(image.unwrap(), (width, height, framebuffer.buffer));
+    }
+
+    #[test]
+    fn test_tiles_cover_every_row() {
+        let mut framebuffer = Framebuffer::new(7, 10);
+
+        let rects: Vec<PixelRect> = framebuffer.tiles_mut(4).map(|tile| tile.rect()).collect();
+
+        assert_eq!(
+            rects,
+            [
+                PixelRect::new(0, 0, 6, 3),
+                PixelRect::new(0, 4, 6, 7),
+                PixelRect::new(0, 8, 6, 9)
+            ]
+        );
+        assert_eq!(Framebuffer::new(0, 10).tiles_mut(4).count(), 0);
+        assert_eq!(Framebuffer::new(7, 0).tiles_mut(4).count(), 0);
+    }
+
+    #[test]
+    #[should_panic = "A tile needs at least one row"]
+    fn test_tiles_need_rows() {
+        Framebuffer::new(7, 10).tiles_mut(0).count();
+    }
+
+    #[test]
+    fn test_load_background() {
+        let mut painted = Framebuffer::new(5, 3);
+        for x in 0..5 {
+            painted.set_current_color(0x202020 * x as u32);
+            painted
+                .paint_point(nalgebra_glm::vec2(x as f32, 1.0), 0.0)
+                .unwrap();
+        }
+        let path = temp_path("background.bmp");
+        let path = path.to_str().unwrap();
+        painted.save(path).unwrap();
+
+        let mut framebuffer = Framebuffer::new(5, 3);
+        let loaded = framebuffer.load_background(path);
+        let mismatched = Framebuffer::new(3, 5).load_background(path);
+        std::fs::remove_file(path).unwrap();
+
+        loaded.unwrap();
+        framebuffer
+            .paint_point(nalgebra_glm::vec2(0.0, 0.0), 0.0)
+            .unwrap();
+        framebuffer.clear();
+        assert_eq!(framebuffer.buffer, painted.buffer);
+        assert_eq!(
+            mismatched.unwrap_err().kind(),
+            std::io::ErrorKind::InvalidInput
+        );
+    }
+}
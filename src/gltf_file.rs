@@ -0,0 +1,533 @@
+//! Meshes of glTF 2.0 files, both `.gltf` (with embedded or external buffers) and `.glb`.
+//!
+//! Every triangle primitive of the scene becomes a `Mesh`, with the transforms of its nodes baked
+//! into the vertices, so the models are placed like in the program they were exported from.
+
+use std::{
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use gltf::{buffer::Source, mesh::Mode, Gltf, Node};
+use nalgebra_glm::{vec2, vec4, Mat4, Vec2, Vec3};
+
+use crate::{
+    color::Color,
+    material::Material,
+    normals::{generate_normals, NormalStrategy},
+    obj::Mesh,
+    shader::create_normal_matrix,
+    vertex::Vertex,
+};
+
+#[derive(Debug)]
+pub enum GltfError {
+    /// The glTF file, or one of its external buffers, couldn't be read.
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    /// The file isn't valid glTF.
+    Parse { error: gltf::Error },
+    /// The data of a buffer is missing or shorter than declared.
+    Buffer { index: usize, message: String },
+    /// A primitive of the `mesh` has no positions.
+    MissingPositions { mesh: String },
+    /// A primitive of the `mesh` uses a vertex that doesn't exist.
+    IndexOutOfRange { mesh: String },
+    /// The scene has no triangles to draw.
+    EmptyMesh,
+}
+
+impl Display for GltfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GltfError::Io { path, error } => {
+                write!(f, "Couldn't read `{}`: {error}", path.display())
+            }
+            GltfError::Parse { error } => write!(f, "Malformed glTF: {error}"),
+            GltfError::Buffer { index, message } => write!(f, "Buffer {index}: {message}"),
+            GltfError::MissingPositions { mesh } => {
+                write!(f, "A primitive of the mesh `{mesh}` has no positions")
+            }
+            GltfError::IndexOutOfRange { mesh } => {
+                write!(
+                    f,
+                    "A primitive of the mesh `{mesh}` uses an index out of range"
+                )
+            }
+            GltfError::EmptyMesh => write!(f, "The glTF scene has no triangles"),
+        }
+    }
+}
+
+impl std::error::Error for GltfError {}
+
+/// Loads the meshes of the default scene of the glTF file at `path`.
+/// Buffers stored in other files are relative to the directory of `path`.
+pub fn load_gltf(path: impl AsRef<Path>) -> Result<Vec<Mesh>, GltfError> {
+    let path = path.as_ref();
+    let source = fs::read(path).map_err(|error| GltfError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+
+    parse_gltf(&source, base_dir)
+}
+
+/// Parses the meshes of the default scene of a `.gltf` or `.glb` file.
+///
+/// Files without scenes use every node that isn't the child of another. Primitives that aren't
+/// triangle lists (points, lines, strips...) are skipped.
+///
+/// * `base_dir`: The directory the paths of external buffers are relative to.
+pub fn parse_gltf(source: &[u8], base_dir: &Path) -> Result<Vec<Mesh>, GltfError> {
+    let Gltf { document, blob } =
+        Gltf::from_slice(source).map_err(|error| GltfError::Parse { error })?;
+
+    let buffers = document
+        .buffers()
+        .map(|buffer| {
+            let index = buffer.index();
+            let data = match buffer.source() {
+                Source::Bin => blob.clone().ok_or_else(|| GltfError::Buffer {
+                    index,
+                    message: "The GLB binary chunk is missing".to_string(),
+                })?,
+                Source::Uri(uri) => read_uri(uri, base_dir).map_err(|error| match error {
+                    UriError::Io(error) => GltfError::Io {
+                        path: base_dir.join(uri),
+                        error,
+                    },
+                    UriError::Invalid(message) => GltfError::Buffer { index, message },
+                })?,
+            };
+
+            if data.len() < buffer.length() {
+                return Err(GltfError::Buffer {
+                    index,
+                    message: format!(
+                        "It has {} bytes, but declares {}",
+                        data.len(),
+                        buffer.length()
+                    ),
+                });
+            }
+            Ok(data)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    check_views(&document)?;
+
+    let roots: Vec<Node> = match document.default_scene().or(document.scenes().next()) {
+        Some(scene) => scene.nodes().collect(),
+        None => {
+            let children: Vec<usize> = document
+                .nodes()
+                .flat_map(|node| node.children().map(|child| child.index()))
+                .collect();
+            document
+                .nodes()
+                .filter(|node| !children.contains(&node.index()))
+                .collect()
+        }
+    };
+
+    let mut meshes = vec![];
+    for node in roots {
+        read_node(&node, &Mat4::identity(), &buffers, &mut meshes)?;
+    }
+
+    if meshes.is_empty() {
+        return Err(GltfError::EmptyMesh);
+    }
+
+    Ok(meshes)
+}
+
+/// Reads the meshes of the `node` and its children.
+///
+/// * `parent_matrix`: The transform of the parent of the node, relative to the scene.
+fn read_node(
+    node: &Node,
+    parent_matrix: &Mat4,
+    buffers: &[Vec<u8>],
+    meshes: &mut Vec<Mesh>,
+) -> Result<(), GltfError> {
+    let matrix = parent_matrix * Mat4::from(node.transform().matrix());
+
+    if let Some(mesh) = node.mesh() {
+        let normal_matrix = create_normal_matrix(&matrix);
+        // Mirroring transforms turn the triangles inside out.
+        let is_mirrored = matrix.fixed_view::<3, 3>(0, 0).determinant() < 0.0;
+
+        let name = || mesh.name().unwrap_or_default().to_string();
+
+        for primitive in mesh.primitives().filter(|p| p.mode() == Mode::Triangles) {
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+            let positions: Vec<Vec3> = reader
+                .read_positions()
+                .ok_or_else(|| GltfError::MissingPositions { mesh: name() })?
+                .map(Vec3::from)
+                .collect();
+            let normals: Option<Vec<Vec3>> = reader
+                .read_normals()
+                .map(|normals| normals.map(Vec3::from).collect());
+            // glTF puts the origin of the texture on its top left corner, OBJ on the bottom left.
+            let tex_coords: Vec<Vec2> = reader
+                .read_tex_coords(0)
+                .map(|tex_coords| {
+                    tex_coords
+                        .into_f32()
+                        .map(|[u, v]| vec2(u, 1.0 - v))
+                        .collect()
+                })
+                .unwrap_or_default();
+            let mut indices: Vec<u32> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..positions.len() as u32).collect(),
+            };
+            indices.truncate(indices.len() / 3 * 3);
+            if indices.is_empty() {
+                continue;
+            }
+            if indices.iter().any(|&idx| idx as usize >= positions.len()) {
+                return Err(GltfError::IndexOutOfRange { mesh: name() });
+            }
+            if is_mirrored {
+                indices
+                    .chunks_exact_mut(3)
+                    .for_each(|triangle| triangle.swap(1, 2));
+            }
+
+            let vertices = positions
+                .iter()
+                .enumerate()
+                .map(|(idx, position)| {
+                    let position = (matrix * vec4(position.x, position.y, position.z, 1.0)).xyz();
+                    let normal = normals
+                        .as_ref()
+                        .and_then(|normals| normals.get(idx))
+                        .map_or(Vec3::zeros(), |n| {
+                            // Zero normals in the file stay zero instead of turning into NaN.
+                            (normal_matrix * vec4(n.x, n.y, n.z, 0.0))
+                                .xyz()
+                                .try_normalize(f32::EPSILON)
+                                .unwrap_or_else(Vec3::zeros)
+                        });
+                    let tex_coords = tex_coords.get(idx).copied().unwrap_or_default();
+
+                    Vertex::new(position, normal, tex_coords)
+                })
+                .collect();
+
+            let mut mesh = Mesh {
+                material: Some(pbr_material(&primitive.material())),
+                ..Mesh::new(vertices, indices)
+            };
+            if normals.is_none() {
+                generate_normals(&mut mesh, NormalStrategy::default());
+            }
+            meshes.push(mesh);
+        }
+    }
+
+    for child in node.children() {
+        read_node(&child, &matrix, buffers, meshes)?;
+    }
+
+    Ok(())
+}
+
+/// Checks that every buffer view fits in its buffer, and every accessor in its view.
+///
+/// The reader of `gltf` silently returns nothing for data out of bounds, which would be reported
+/// as missing attributes instead.
+fn check_views(document: &gltf::Document) -> Result<(), GltfError> {
+    for view in document.views() {
+        let (buffer, end) = (view.buffer(), view.offset() + view.length());
+        if end > buffer.length() {
+            return Err(GltfError::Buffer {
+                index: buffer.index(),
+                message: format!(
+                    "The buffer view {} ends at byte {end}, but the buffer has {}",
+                    view.index(),
+                    buffer.length()
+                ),
+            });
+        }
+    }
+
+    for accessor in document.accessors() {
+        let Some(view) = accessor.view() else {
+            continue;
+        };
+        if accessor.count() == 0 {
+            continue;
+        }
+        let stride = view.stride().unwrap_or(accessor.size());
+        let end = accessor.offset() + (accessor.count() - 1) * stride + accessor.size();
+        if end > view.length() {
+            return Err(GltfError::Buffer {
+                index: view.buffer().index(),
+                message: format!(
+                    "The accessor {} ends at byte {end}, but its buffer view {} has {}",
+                    accessor.index(),
+                    view.index(),
+                    view.length()
+                ),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Maps a metallic-roughness material into a `Material`.
+///
+/// The base color factor becomes the diffuse color and its alpha the opacity. Rough materials
+/// get a wide and dim specular highlight, and metallic ones are reflective.
+fn pbr_material(material: &gltf::Material) -> Material {
+    let pbr = material.pbr_metallic_roughness();
+    let [r, g, b, alpha] = pbr
+        .base_color_factor()
+        .map(|c| (c.clamp(0.0, 1.0) * 255.0).round());
+    let roughness = pbr.roughness_factor().clamp(0.0, 1.0);
+
+    Material {
+        diffuse: Color::new(r as u8, g as u8, b as u8),
+        // The usual mapping of roughness into a Blinn-Phong exponent.
+        specular: (2.0 / roughness.powi(4).max(1e-3) - 2.0).min(2000.0),
+        albedo: (1.0, 1.0 - roughness),
+        reflectivity: pbr.metallic_factor().clamp(0.0, 1.0),
+        transparency: 1.0 - alpha / 255.0,
+        // The default index of refraction of glTF.
+        refractive_index: 1.5,
+    }
+}
+
+enum UriError {
+    Io(std::io::Error),
+    Invalid(String),
+}
+
+/// Reads the data of a buffer, either embedded in a base64 data URI or from a file relative to
+/// `base_dir`.
+fn read_uri(uri: &str, base_dir: &Path) -> Result<Vec<u8>, UriError> {
+    let Some(data) = uri.strip_prefix("data:") else {
+        return fs::read(base_dir.join(uri)).map_err(UriError::Io);
+    };
+
+    let (_, encoded) = data
+        .split_once(";base64,")
+        .ok_or_else(|| UriError::Invalid("Only base64 data URIs are supported".to_string()))?;
+    STANDARD
+        .decode(encoded)
+        .map_err(|error| UriError::Invalid(format!("Invalid base64 data: {error}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra_glm::vec3;
+
+    /// A right triangle on the XY plane, facing +Z, with UVs and a single index buffer.
+    fn triangle_buffer() -> Vec<u8> {
+        let positions = [[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+        let tex_coords = [[0.0f32, 1.0], [1.0, 1.0], [0.0, 0.0]];
+        let indices = [0u16, 1, 2, 0];
+
+        positions
+            .iter()
+            .flatten()
+            .chain(tex_coords.iter().flatten())
+            .flat_map(|c| c.to_le_bytes())
+            .chain(indices.iter().flat_map(|i| i.to_le_bytes()))
+            .collect()
+    }
+
+    /// The JSON of a file with the triangle, placed by a parent and a child node.
+    ///
+    /// * `buffer`: The JSON of the only buffer of the file.
+    fn triangle_json(buffer: &str) -> String {
+        format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "scene": 0,
+                "scenes": [{{ "nodes": [0] }}],
+                "nodes": [
+                    {{ "translation": [0, 0, -5], "children": [1] }},
+                    {{ "mesh": 0, "scale": [2, 2, 2] }}
+                ],
+                "meshes": [{{
+                    "name": "triangle",
+                    "primitives": [{{
+                        "attributes": {{ "POSITION": 0, "TEXCOORD_0": 1 }},
+                        "indices": 2,
+                        "material": 0
+                    }}]
+                }}],
+                "materials": [{{
+                    "pbrMetallicRoughness": {{
+                        "baseColorFactor": [1.0, 0.5, 0.0, 0.75],
+                        "metallicFactor": 0.0,
+                        "roughnessFactor": 1.0
+                    }}
+                }}],
+                "buffers": [{buffer}],
+                "bufferViews": [
+                    {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+                    {{ "buffer": 0, "byteOffset": 36, "byteLength": 24 }},
+                    {{ "buffer": 0, "byteOffset": 60, "byteLength": 6 }}
+                ],
+                "accessors": [
+                    {{
+                        "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                        "min": [0, 0, 0], "max": [1, 1, 0]
+                    }},
+                    {{ "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC2" }},
+                    {{ "bufferView": 2, "componentType": 5123, "count": 3, "type": "SCALAR" }}
+                ]
+            }}"#
+        )
+    }
+
+    fn glb(json: &str, bin: &[u8]) -> Vec<u8> {
+        let pad = |mut chunk: Vec<u8>, byte: u8| {
+            chunk.resize(chunk.len().div_ceil(4) * 4, byte);
+            chunk
+        };
+        let json = pad(json.as_bytes().to_vec(), b' ');
+        let bin = pad(bin.to_vec(), 0);
+        let length = 12 + 8 + json.len() + 8 + bin.len();
+
+        let mut glb = vec![];
+        glb.extend(b"glTF");
+        glb.extend(2u32.to_le_bytes());
+        glb.extend((length as u32).to_le_bytes());
+        glb.extend((json.len() as u32).to_le_bytes());
+        glb.extend(b"JSON");
+        glb.extend(json);
+        glb.extend((bin.len() as u32).to_le_bytes());
+        glb.extend(b"BIN\0");
+        glb.extend(bin);
+
+        glb
+    }
+
+    fn assert_is_placed_triangle(meshes: &[Mesh]) {
+        assert_eq!(meshes.len(), 1);
+        let mesh = &meshes[0];
+        assert_eq!(mesh.triangle_count(), 1);
+
+        let [a, b, c] = [0, 1, 2].map(|i| &mesh.vertices[mesh.indices[i] as usize]);
+        // Scaled by the child and moved by the parent.
+        assert_eq!(a.position, vec3(0.0, 0.0, -5.0));
+        assert_eq!(b.position, vec3(2.0, 0.0, -5.0));
+        assert_eq!(c.position, vec3(0.0, 2.0, -5.0));
+        // The V axis is flipped into the convention of OBJ.
+        assert_eq!(a.tex_coords, vec2(0.0, 0.0));
+        assert_eq!(c.tex_coords, vec2(0.0, 1.0));
+        // The file has no normals, so they are generated facing the front of the triangle.
+        assert_eq!(mesh.generated_normals, Some(NormalStrategy::default()));
+        assert!((a.normal - vec3(0.0, 0.0, 1.0)).magnitude() < 1e-5);
+
+        let material = mesh.material.unwrap();
+        assert_eq!(material.diffuse, Color::new(255, 128, 0));
+        assert!((material.transparency - 0.25).abs() < 1e-2);
+        assert_eq!(material.reflectivity, 0.0);
+    }
+
+    #[test]
+    fn test_gltf_with_embedded_buffer() {
+        let buffer = format!(
+            r#"{{ "byteLength": 66, "uri": "data:application/octet-stream;base64,{}" }}"#,
+            STANDARD.encode(triangle_buffer())
+        );
+
+        let meshes = parse_gltf(triangle_json(&buffer).as_bytes(), Path::new("")).unwrap();
+
+        assert_is_placed_triangle(&meshes);
+    }
+
+    #[test]
+    fn test_glb() {
+        let json = triangle_json(r#"{ "byteLength": 66 }"#);
+
+        let meshes = parse_gltf(&glb(&json, &triangle_buffer()), Path::new("")).unwrap();
+
+        assert_is_placed_triangle(&meshes);
+    }
+
+    #[test]
+    fn test_zero_normals() {
+        // The triangle with normals after its indices, the second one zero.
+        let mut buffer = triangle_buffer();
+        buffer.resize(68, 0);
+        let normals = [[0.0f32, 0.0, 1.0], [0.0, 0.0, 0.0], [0.0, 0.0, 1.0]];
+        buffer.extend(normals.iter().flatten().flat_map(|c| c.to_le_bytes()));
+        let json = triangle_json(r#"{ "byteLength": 104 }"#)
+            .replace(r#""TEXCOORD_0": 1"#, r#""TEXCOORD_0": 1, "NORMAL": 3"#)
+            .replace(
+                r#""byteLength": 6 }"#,
+                r#""byteLength": 6 },
+                    { "buffer": 0, "byteOffset": 68, "byteLength": 36 }"#,
+            )
+            .replace(
+                r#""type": "SCALAR" }"#,
+                r#""type": "SCALAR" },
+                    { "bufferView": 3, "componentType": 5126, "count": 3, "type": "VEC3" }"#,
+            );
+
+        let meshes = parse_gltf(&glb(&json, &buffer), Path::new("")).unwrap();
+
+        let mesh = &meshes[0];
+        assert_eq!(mesh.generated_normals, None);
+        let normals: Vec<Vec3> = mesh.vertices.iter().map(|v| v.normal).collect();
+        assert_eq!(
+            normals,
+            [vec3(0.0, 0.0, 1.0), Vec3::zeros(), vec3(0.0, 0.0, 1.0)]
+        );
+    }
+
+    #[test]
+    fn test_missing_data() {
+        let json = triangle_json(r#"{ "byteLength": 66 }"#);
+        let short = glb(&json, &triangle_buffer()[..32]);
+
+        assert!(matches!(
+            parse_gltf(json.as_bytes(), Path::new("")),
+            Err(GltfError::Buffer { index: 0, .. })
+        ));
+        assert!(matches!(
+            parse_gltf(&short, Path::new("")),
+            Err(GltfError::Buffer { index: 0, .. })
+        ));
+        // Views and accessors past the end of their data are reported as such, not as missing.
+        let long_view = json.replace(
+            r#""byteOffset": 60, "byteLength": 6"#,
+            r#""byteOffset": 64, "byteLength": 6"#,
+        );
+        let long_accessor = json.replace(
+            r#""byteOffset": 0, "byteLength": 36"#,
+            r#""byteOffset": 0, "byteLength": 24"#,
+        );
+        for json in [long_view, long_accessor] {
+            let error = parse_gltf(&glb(&json, &triangle_buffer()), Path::new(""));
+            assert!(
+                matches!(error, Err(GltfError::Buffer { index: 0, .. })),
+                "{error:?}"
+            );
+        }
+        assert!(matches!(
+            parse_gltf(b"{ not json", Path::new("")),
+            Err(GltfError::Parse { .. })
+        ));
+        assert!(matches!(
+            load_gltf("does_not_exist.glb"),
+            Err(GltfError::Io { .. })
+        ));
+    }
+}
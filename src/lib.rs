@@ -1,25 +1,40 @@
+use assets::ObjHandle;
 use blenders::BlendMode;
 use camera::Camera;
-use color::Color;
-use fastnoise_lite::FastNoiseLite;
 use nalgebra_glm::{Mat4, Vec2, Vec3};
-use obj::Obj;
-use shader::{ShaderType, Uniforms};
+use scene::SceneNode;
+use shader::{FragmentShader, Uniforms, VertexShader};
+use solar_system::Orbit;
 
+pub mod assets;
 pub mod blenders;
 pub mod bmp;
 pub mod camera;
+pub mod clipping;
 pub mod color;
 pub mod fragment;
+pub mod fragment_shaders;
 pub mod framebuffer;
+pub mod gltf_file;
 pub mod light;
 pub mod material;
+pub mod normals;
 pub mod obj;
 pub mod planets;
+pub mod ply;
+pub mod primitives;
 pub mod render;
+pub mod scene;
+pub mod scene_file;
 pub mod shader;
+pub mod solar_system;
+pub mod stl;
+pub mod texture;
 pub mod vertex;
 
+#[cfg(test)]
+mod test_utils;
+
 pub fn equal(a: f32, b: f32, eps: f32) -> bool {
     (a - b).abs() < eps
 }
@@ -35,19 +50,28 @@ pub enum Message {
     ZoomCamera(f32),
     UpdateTime(f32),
     ChangePlanet(Entity),
+    ShowSolarSystem,
+    ReloadScene(Box<Model>),
 }
 
-pub type EntityShader = (ShaderType, Vec<Color>, BlendMode);
+pub type EntityShader = (Box<dyn FragmentShader>, BlendMode);
 
 pub struct Entity {
-    pub objs: Vec<Obj>,
+    pub objs: ObjHandle,
+    pub vertex_shader: Box<dyn VertexShader>,
     pub shaders: Vec<EntityShader>,
     pub model_matrix: Mat4,
+    /// When set, the `model_matrix` follows this orbit as time goes by.
+    pub orbit: Option<Orbit>,
 }
 
 pub struct Model {
+    /// The bodies of the solar system, animated every frame.
     pub entities: Vec<Entity>,
+    /// Entities rendered on their own, like the planet being showcased.
     pub render_entities: Vec<Entity>,
+    /// Entities placed relative to each other, rendered along the other entities.
+    pub scene: SceneNode,
     pub uniforms: Uniforms,
     pub rotation: Vec3,
     pub translation: Vec3,
@@ -1,11 +1,51 @@
 use crate::color::Color;
 use nalgebra_glm::Vec3;
 
+#[derive(Debug, Clone)]
+pub enum LightKind {
+    /// Light emitted in every direction from a `position` in world space.
+    Point { position: Vec3 },
+    /// Light coming from infinitely far away, every ray travels in the same `direction`.
+    Directional { direction: Vec3 },
+}
+
 #[derive(Debug, Clone)]
 pub struct Light {
-    pub position: Vec3,
+    pub kind: LightKind,
     pub color: Color,
     pub intensity: f32,
 }
 
+impl Light {
+    /// Creates a light located at `position` that shines in every direction.
+    pub fn point(position: Vec3, color: Color, intensity: f32) -> Self {
+        Light {
+            kind: LightKind::Point { position },
+            color,
+            intensity,
+        }
+    }
+
+    /// Creates a light whose rays all travel in the given `direction`.
+    pub fn directional(direction: Vec3, color: Color, intensity: f32) -> Self {
+        Light {
+            kind: LightKind::Directional {
+                direction: direction.normalize(),
+            },
+            color,
+            intensity,
+        }
+    }
+
+    /// Computes the normalized direction that goes from `point` towards the light.
+    ///
+    /// * `point`: A point in world space.
+    pub fn direction_from(&self, point: &Vec3) -> Vec3 {
+        match &self.kind {
+            LightKind::Point { position } => (position - point).normalize(),
+            LightKind::Directional { direction } => -direction,
+        }
+    }
+}
+
 pub type AmbientLightIntensity = f32;
@@ -1,28 +1,28 @@
-use fastnoise_lite::FastNoiseLite;
 use minifb::{Key, KeyRepeat, Window, WindowOptions};
-use mouse_rs::Mouse;
 use nalgebra_glm::{vec3, Vec3};
 use std::collections::VecDeque;
 use std::f32::consts::PI;
 use std::time::{Duration, Instant};
-use three_d_rendering::blenders::BlendMode;
 use three_d_rendering::camera::Camera;
 use three_d_rendering::color::Color;
-use three_d_rendering::obj::load_objs;
+use three_d_rendering::framebuffer;
+use three_d_rendering::light::Light;
 use three_d_rendering::planets::{
     create_disco_planet, create_face_planet, create_gas_giant, create_green_planet,
     create_ocean_planet, create_snow_planet, create_sun,
 };
 use three_d_rendering::render::render;
+use three_d_rendering::scene::SceneNode;
+use three_d_rendering::scene_file::{load_scene, reload_scene, SceneWatcher};
 use three_d_rendering::shader::{
-    create_model_matrix, create_noise, create_projection_matrix, create_view_matrix,
-    create_viewport_matrix, ShaderType, Uniforms,
+    create_projection_matrix, create_view_matrix, create_viewport_matrix, Uniforms,
 };
-use three_d_rendering::{framebuffer, Entity};
+use three_d_rendering::solar_system::{create_solar_system, create_sun_light, update_orbits};
 use three_d_rendering::{Message, Model};
 
 const ZOOM_SPEED: f32 = 1.0;
 const ROTATION_SPEED: f32 = PI / 20.0;
+const AMBIENT_INTENSITY: f32 = 0.05;
 
 fn main() {
     let window_width = 1080;
@@ -48,7 +48,6 @@ fn main() {
         Window::new(title_prefix, window_width, window_height, window_options).unwrap();
     window.set_key_repeat_delay(0.01);
     window.set_cursor_visibility(true);
-    let mouse = Mouse::new();
 
     let target_framerate = 60;
     let frame_delay = Duration::from_millis(1000 / target_framerate);
@@ -57,9 +56,20 @@ fn main() {
         (window_width, window_height),
         (framebuffer_width, framebuffer_height),
     );
-    let mut noise = FastNoiseLite::with_seed(1506);
-    noise.set_frequency(Some(0.004));
-    render(&mut framebuffer, &data, &mut noise);
+
+    // The scene file given as argument is reloaded every time it changes.
+    let mut scene_watcher = std::env::args().nth(1).map(SceneWatcher::new);
+    let mut scene_error = None;
+    if let Some(watcher) = &scene_watcher {
+        match load_scene(watcher.path(), (framebuffer_width, framebuffer_height)) {
+            Ok(scene) => data = scene,
+            Err(error) => {
+                eprintln!("{error}");
+                scene_error = Some(error.summary());
+            }
+        }
+    }
+    render(&mut framebuffer, &data);
 
     let mut splash_timer = 0;
     let splash_delay = 300;
@@ -71,7 +81,6 @@ fn main() {
     let mut last_recorded_frames = VecDeque::with_capacity(last_recorded_frames_max_count);
     let mut time = 0.0;
     while window.is_open() {
-        let mut should_update = false;
         let start = Instant::now();
         mode_cooldown_timer = (mode_cooldown_timer - 1).max(0);
         splash_timer = (splash_timer + 1).min(splash_delay + 1);
@@ -100,6 +109,10 @@ fn main() {
                 Key::Key5 => Some(Message::ChangePlanet(create_snow_planet())),
                 Key::Key6 => Some(Message::ChangePlanet(create_sun())),
                 Key::Key7 => Some(Message::ChangePlanet(create_green_planet())),
+                Key::Key0 if mode_cooldown_timer == 0 => {
+                    mode_cooldown_timer = mode_cooldown;
+                    Some(Message::ShowSolarSystem)
+                }
 
                 // Key::Tab => {
                 //     should_update = true;
@@ -126,17 +139,30 @@ fn main() {
                 _ => None,
             })
             .collect();
-        should_update = true;
         messages.push(Message::UpdateTime(time));
 
+        if let Some(watcher) = &mut scene_watcher {
+            if watcher.has_changed() {
+                match load_scene(watcher.path(), (framebuffer_width, framebuffer_height)) {
+                    Ok(scene) => {
+                        messages.push(Message::ReloadScene(Box::new(scene)));
+                        scene_error = None;
+                    }
+                    Err(error) => {
+                        eprintln!("{error}");
+                        scene_error = Some(error.summary());
+                    }
+                }
+            }
+        }
+
         for msg in messages {
             data = update(data, msg);
         }
 
-        if data.camera.has_changed() || should_update {
-            framebuffer.clear();
-            render(&mut framebuffer, &data, &mut noise);
-        }
+        // Time moves every frame, so there's always something new to render.
+        framebuffer.clear();
+        render(&mut framebuffer, &data);
         data.camera.reset_change();
 
         // Update the window with the framebuffer contents
@@ -154,14 +180,18 @@ fn main() {
         let avg_millis: f32 = last_recorded_frames.iter().map(|&u| u as f32).sum::<f32>()
             / last_recorded_frames_max_count as f32;
         let avg_frames = 1000.0 / avg_millis;
-        window.set_title(format!("{} - {:.2} fps", title_prefix, avg_frames).as_ref());
+        let title = match &scene_error {
+            Some(error) => format!("{} - {:.2} fps - {}", title_prefix, avg_frames, error),
+            None => format!("{} - {:.2} fps", title_prefix, avg_frames),
+        };
+        window.set_title(&title);
         std::thread::sleep(frame_delay);
     }
 }
 
 /// Init the default state
 fn init(window_dimensions: (usize, usize), framebuffer_dimensions: (usize, usize)) -> Model {
-    let (framebuffer_height, framebuffer_width) = framebuffer_dimensions;
+    let (framebuffer_width, framebuffer_height) = framebuffer_dimensions;
     let (window_width, window_height) = window_dimensions;
 
     let start_planet = create_green_planet();
@@ -184,6 +214,7 @@ fn init(window_dimensions: (usize, usize), framebuffer_dimensions: (usize, usize
     Model {
         entities,
         render_entities,
+        scene: SceneNode::default(),
         uniforms: Uniforms {
             view_matrix: create_view_matrix(camera.eye, camera.center, camera.up),
             projection_matrix: create_projection_matrix(window_width as f32, window_height as f32),
@@ -192,6 +223,9 @@ fn init(window_dimensions: (usize, usize), framebuffer_dimensions: (usize, usize
                 framebuffer_height as f32,
             ),
             time: 0.0,
+            camera_position: camera.eye,
+            lights: vec![create_planet_light()],
+            ambient_intensity: AMBIENT_INTENSITY,
         },
         rotation,
         translation,
@@ -200,6 +234,11 @@ fn init(window_dimensions: (usize, usize), framebuffer_dimensions: (usize, usize
     }
 }
 
+/// The light used when showcasing a single planet.
+fn create_planet_light() -> Light {
+    Light::directional(vec3(0.0, -0.5, -1.0), Color::white(), 1.0)
+}
+
 fn update(data: Model, msg: Message) -> Model {
     match msg {
         Message::RotateCamera(delta_yaw, delta_pitch) => {
@@ -213,6 +252,7 @@ fn update(data: Model, msg: Message) -> Model {
             camera.orbit(delta_yaw, delta_pitch);
             let uniforms = Uniforms {
                 view_matrix: create_view_matrix(camera.eye, camera.center, camera.up),
+                camera_position: camera.eye,
                 ..uniforms
             };
 
@@ -234,6 +274,7 @@ fn update(data: Model, msg: Message) -> Model {
             camera.zoom(delta_zoom);
             let uniforms = Uniforms {
                 view_matrix: create_view_matrix(camera.eye, camera.center, camera.up),
+                camera_position: camera.eye,
                 ..uniforms
             };
 
@@ -245,17 +286,60 @@ fn update(data: Model, msg: Message) -> Model {
             }
         }
         Message::UpdateTime(time) => {
-            let Model { uniforms, .. } = data;
+            let Model {
+                mut entities,
+                uniforms,
+                ..
+            } = data;
 
+            update_orbits(&mut entities, time);
             let uniforms = Uniforms { time, ..uniforms };
 
-            Model { uniforms, ..data }
+            Model {
+                entities,
+                uniforms,
+                ..data
+            }
         }
+        Message::ReloadScene(scene) => reload_scene(data, *scene),
         Message::ChangePlanet(entity) => {
+            let Model { uniforms, .. } = data;
+
             let render_entities = vec![entity];
+            let uniforms = Uniforms {
+                lights: vec![create_planet_light()],
+                ..uniforms
+            };
 
             Model {
+                entities: vec![],
                 render_entities,
+                uniforms,
+                ..data
+            }
+        }
+        Message::ShowSolarSystem => {
+            let Model { uniforms, .. } = data;
+
+            let camera = Camera::new(
+                Vec3::new(0.0, 25.0, 55.0),
+                Vec3::zeros(),
+                Vec3::new(0.0, 1.0, 0.0),
+            );
+            let mut entities = create_solar_system();
+            update_orbits(&mut entities, uniforms.time);
+            let uniforms = Uniforms {
+                view_matrix: create_view_matrix(camera.eye, camera.center, camera.up),
+                camera_position: camera.eye,
+                lights: vec![create_sun_light()],
+                ..uniforms
+            };
+
+            Model {
+                entities,
+                render_entities: vec![],
+                uniforms,
+                camera,
                 ..data
             }
         }
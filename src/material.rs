@@ -1,6 +1,6 @@
 use crate::color::Color;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy)]
 pub struct Material {
     pub diffuse: Color,
     pub specular: f32,
@@ -29,6 +29,36 @@ impl Material {
     }
 }
 
+/// Maps an MTL material into a `Material`.
+///
+/// `Kd` becomes the diffuse color, `Ns` the specular exponent and the brightness of `Ks` the
+/// specular albedo. `d` (dissolve) is the opposite of the transparency and `Ni` is the refractive
+/// index. Missing values keep the ones of `Material::default`, except for a full diffuse albedo.
+impl From<&tobj::Material> for Material {
+    fn from(material: &tobj::Material) -> Self {
+        let default = Material::default();
+        let specular_albedo = material
+            .specular
+            .map_or(default.albedo.1, |[r, g, b]| (r + g + b) / 3.0);
+
+        Material {
+            diffuse: material.diffuse.map_or(default.diffuse, color_from_rgb),
+            specular: material.shininess.unwrap_or(default.specular),
+            albedo: (1.0, specular_albedo),
+            reflectivity: default.reflectivity,
+            transparency: material.dissolve.map_or(default.transparency, |d| 1.0 - d),
+            refractive_index: material.optical_density.unwrap_or(default.refractive_index),
+        }
+    }
+}
+
+/// Converts a color with components between 0 and 1 into a `Color`.
+fn color_from_rgb(rgb: [f32; 3]) -> Color {
+    let [r, g, b] = rgb.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+
+    Color::new(r, g, b)
+}
+
 pub const DIRT: Material = Material {
     diffuse: Color::pink(),
     specular: 1.0,
@@ -109,3 +139,36 @@ pub const RUBBER: Material = Material {
     transparency: 0.0,
     refractive_index: 1.51,
 };
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_mtl() {
+        let mtl = tobj::Material {
+            diffuse: Some([0.8, 0.4, 0.0]),
+            specular: Some([0.5, 0.5, 0.5]),
+            shininess: Some(250.0),
+            dissolve: Some(0.75),
+            optical_density: Some(1.45),
+            ..Default::default()
+        };
+
+        let material = Material::from(&mtl);
+
+        assert_eq!(material.diffuse, Color::new(204, 102, 0));
+        assert_eq!(material.specular, 250.0);
+        assert_eq!(material.albedo, (1.0, 0.5));
+        assert_eq!(material.transparency, 0.25);
+        assert_eq!(material.refractive_index, 1.45);
+    }
+
+    #[test]
+    fn test_from_empty_mtl() {
+        let material = Material::from(&tobj::Material::default());
+
+        assert_eq!(material.diffuse, Material::default().diffuse);
+        assert_eq!(material.transparency, 0.0);
+    }
+}
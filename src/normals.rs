@@ -0,0 +1,245 @@
+use std::{collections::HashMap, f32::consts::PI};
+
+use nalgebra_glm::{angle, cross, dot, Vec3};
+
+use crate::obj::Mesh;
+
+/// How to compute the normals of a mesh that was loaded without them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NormalStrategy {
+    /// Every triangle uses its face normal, so the mesh looks faceted.
+    Flat,
+    /// Every vertex averages the normals of the triangles around it, weighted by the angle of
+    /// each triangle at that vertex. Triangles whose normals differ by more than `crease_angle`
+    /// (in radians) don't get averaged, so hard edges stay sharp.
+    Smooth { crease_angle: f32 },
+}
+
+impl Default for NormalStrategy {
+    fn default() -> Self {
+        NormalStrategy::Smooth {
+            crease_angle: PI / 3.0,
+        }
+    }
+}
+
+/// Replaces the normals of every vertex of the `mesh`, using the given `strategy`, and records
+/// it in `Mesh::generated_normals`.
+///
+/// Vertices are split wherever a vertex needs a different normal on each of its triangles, and
+/// triangles sharing a position are smoothed together even if their vertices were split by the
+/// texture coordinates.
+pub fn generate_normals(mesh: &mut Mesh, strategy: NormalStrategy) {
+    let triangles: Vec<[usize; 3]> = mesh
+        .indices
+        .chunks_exact(3)
+        .map(|triangle| [0, 1, 2].map(|i| triangle[i] as usize))
+        .collect();
+    let faces: Vec<(Vec3, [f32; 3])> = triangles
+        .iter()
+        .map(|triangle| face_normal_and_angles(triangle.map(|idx| mesh.vertices[idx].position)))
+        .collect();
+
+    let mut corners_at: HashMap<[u32; 3], Vec<(usize, usize)>> = HashMap::new();
+    for (face, triangle) in triangles.iter().enumerate() {
+        for (corner, &idx) in triangle.iter().enumerate() {
+            corners_at
+                .entry(position_key(&mesh.vertices[idx].position))
+                .or_default()
+                .push((face, corner));
+        }
+    }
+
+    let corner_normal = |face: usize, corner: usize| {
+        let (normal, _) = faces[face];
+        let NormalStrategy::Smooth { crease_angle } = strategy else {
+            return normal;
+        };
+
+        let position = mesh.vertices[triangles[face][corner]].position;
+        let min_cos = crease_angle.cos();
+        let sum = corners_at[&position_key(&position)]
+            .iter()
+            .map(|&(other, other_corner)| (faces[other], other_corner))
+            .filter(|((other_normal, _), _)| dot(&normal, other_normal) >= min_cos)
+            .fold(
+                Vec3::zeros(),
+                |acc, ((other_normal, angles), other_corner)| {
+                    acc + other_normal * angles[other_corner]
+                },
+            );
+
+        sum.try_normalize(f32::EPSILON).unwrap_or(normal)
+    };
+
+    let mut vertices = Vec::with_capacity(mesh.vertices.len());
+    let mut split = HashMap::new();
+    let mut indices = Vec::with_capacity(mesh.indices.len());
+    for (face, triangle) in triangles.iter().enumerate() {
+        for (corner, &idx) in triangle.iter().enumerate() {
+            let normal = corner_normal(face, corner);
+            let new_idx = *split
+                .entry((idx, position_key(&normal)))
+                .or_insert_with(|| {
+                    let mut vertex = mesh.vertices[idx].clone();
+                    vertex.normal = normal;
+                    vertices.push(vertex);
+                    vertices.len() as u32 - 1
+                });
+            indices.push(new_idx);
+        }
+    }
+
+    mesh.vertices = vertices;
+    mesh.indices = indices;
+    mesh.generated_normals = Some(strategy);
+}
+
+/// The normal of a counter-clockwise triangle and its interior angle at each vertex.
+/// Degenerated triangles have a zero normal, so they don't add to their neighbors.
+fn face_normal_and_angles([a, b, c]: [Vec3; 3]) -> (Vec3, [f32; 3]) {
+    let normal = cross(&(b - a), &(c - a))
+        .try_normalize(f32::EPSILON)
+        .unwrap_or_else(Vec3::zeros);
+    let corner_angle = |p: Vec3, q: Vec3, r: Vec3| {
+        let (pq, pr) = (q - p, r - p);
+        if pq.magnitude() <= f32::EPSILON || pr.magnitude() <= f32::EPSILON {
+            return 0.0;
+        }
+        angle(&pq, &pr)
+    };
+
+    (
+        normal,
+        [
+            corner_angle(a, b, c),
+            corner_angle(b, c, a),
+            corner_angle(c, a, b),
+        ],
+    )
+}
+
+/// A key to find the vertices on the exact same position.
+pub fn position_key(position: &Vec3) -> [u32; 3] {
+    // Adding 0 turns -0 into 0, so both are the same position.
+    [position.x, position.y, position.z].map(|c| (c + 0.0).to_bits())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vertex::Vertex;
+    use nalgebra_glm::{vec2, vec3};
+
+    /// A cube of side 2 whose faces don't share vertices, like when every face has its own
+    /// texture coordinates.
+    fn cube() -> Mesh {
+        let corners = |normal: Vec3, u: Vec3, v: Vec3| {
+            [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+                .map(|(s, t)| Vertex::new(normal + u * s + v * t, Vec3::zeros(), vec2(s, t)))
+        };
+        let x = vec3(1.0, 0.0, 0.0);
+        let y = vec3(0.0, 1.0, 0.0);
+        let z = vec3(0.0, 0.0, 1.0);
+        let faces = [
+            corners(x, -z, y),
+            corners(-x, z, y),
+            corners(y, x, -z),
+            corners(-y, x, z),
+            corners(z, x, y),
+            corners(-z, -x, y),
+        ];
+
+        let mut mesh = Mesh::default();
+        for face in faces {
+            let first = mesh.vertices.len() as u32;
+            mesh.vertices.extend(face);
+            mesh.indices
+                .extend([0, 1, 2, 0, 2, 3].map(|corner| first + corner));
+        }
+
+        mesh
+    }
+
+    fn assert_normals_match(mesh: &Mesh, expected: impl Fn(&Vertex) -> Vec3) {
+        for vertex in &mesh.vertices {
+            let expected = expected(vertex);
+            assert!(
+                (vertex.normal - expected).magnitude() < 1e-5,
+                "{:?} != {expected:?}",
+                vertex.normal
+            );
+        }
+    }
+
+    #[test]
+    fn test_flat_normals() {
+        let mut mesh = cube();
+        let original = mesh.clone();
+
+        generate_normals(&mut mesh, NormalStrategy::Flat);
+
+        assert_eq!(mesh.generated_normals, Some(NormalStrategy::Flat));
+        // Every face already had its own vertices, so nothing was split.
+        assert_eq!(mesh.vertices.len(), original.vertices.len());
+        for triangle in mesh.triangles(&mesh.vertices) {
+            let [a, b, c] = triangle.map(|v| v.position);
+            let expected = cross(&(b - a), &(c - a)).normalize();
+            for vertex in triangle {
+                assert!((vertex.normal - expected).magnitude() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn test_smooth_normals_point_out_of_the_corners() {
+        let mut mesh = cube();
+
+        // The faces of the cube are 90 degrees apart.
+        generate_normals(
+            &mut mesh,
+            NormalStrategy::Smooth {
+                crease_angle: PI / 2.0 + 0.1,
+            },
+        );
+
+        assert_normals_match(&mesh, |vertex| vertex.position.normalize());
+    }
+
+    #[test]
+    fn test_crease_angle_keeps_hard_edges() {
+        let mut flat = cube();
+        let mut creased = cube();
+
+        generate_normals(&mut flat, NormalStrategy::Flat);
+        generate_normals(&mut creased, NormalStrategy::default());
+
+        assert_eq!(creased.vertices.len(), flat.vertices.len());
+        for (creased, flat) in creased.vertices.iter().zip(&flat.vertices) {
+            assert!((creased.normal - flat.normal).magnitude() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_smoothing_is_weighted_by_angle() {
+        // Two perpendicular faces meeting at the origin with a right angle each, but one of them
+        // is split in two triangles. Weighting by angle makes the split irrelevant.
+        let v = |x, y, z| Vertex::new(vec3(x, y, z), Vec3::zeros(), vec2(0.0, 0.0));
+        let mut mesh = Mesh::new(
+            vec![
+                v(0.0, 0.0, 0.0),
+                v(1.0, 0.0, 0.0),
+                v(0.0, 1.0, 0.0),
+                v(0.0, 1.0, 1.0),
+                v(0.0, 0.0, 1.0),
+            ],
+            vec![0, 1, 2, 0, 2, 3, 0, 3, 4],
+        );
+
+        generate_normals(&mut mesh, NormalStrategy::Smooth { crease_angle: PI });
+
+        let origin = &mesh.vertices[mesh.indices[0] as usize];
+        let expected = vec3(1.0, 0.0, 1.0).normalize();
+        assert!((origin.normal - expected).magnitude() < 1e-5);
+    }
+}
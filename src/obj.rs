@@ -1,24 +1,185 @@
+use std::{
+    fmt::Display,
+    fs,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
 use nalgebra_glm::{Vec2, Vec3};
 use tobj;
 
-use crate::vertex::Vertex;
+use crate::{
+    material::Material,
+    normals::{generate_normals, NormalStrategy},
+    vertex::Vertex,
+};
+
+/// An indexed triangle mesh.
+///
+/// Vertices shared by many triangles are stored once, so they only go through the vertex shader
+/// once. Every 3 consecutive `indices` form a triangle.
+#[derive(Debug, Clone, Default)]
+pub struct Mesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    /// The material the mesh was assigned in its MTL file, if any.
+    pub material: Option<Material>,
+    /// How the normals of the mesh were generated, `None` when they came with the mesh.
+    pub generated_normals: Option<NormalStrategy>,
+}
+
+impl Mesh {
+    pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>) -> Self {
+        Mesh {
+            vertices,
+            indices,
+            material: None,
+            generated_normals: None,
+        }
+    }
+
+    /// Creates a mesh out of a flat list of vertices, where every 3 vertices form a triangle.
+    pub fn from_triangles(vertices: Vec<Vertex>) -> Self {
+        let indices = (0..vertices.len() as u32).collect();
+
+        Mesh::new(vertices, indices)
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    /// Iterates over the triangles of the mesh, taking their vertices out of `vertices`.
+    ///
+    /// * `vertices`: The vertices of the mesh, they can be the result of running the vertex
+    ///   shader over `self.vertices`.
+    pub fn triangles<'a>(
+        &'a self,
+        vertices: &'a [Vertex],
+    ) -> impl Iterator<Item = [&'a Vertex; 3]> + 'a {
+        self.indices.chunks_exact(3).map(move |triangle| {
+            [
+                &vertices[triangle[0] as usize],
+                &vertices[triangle[1] as usize],
+                &vertices[triangle[2] as usize],
+            ]
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum ObjError {
+    /// The OBJ file couldn't be read or written.
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    /// A statement of the OBJ file is malformed.
+    Parse { error: tobj::LoadError },
+    /// A face refers to an `attribute` (position, normal...) that isn't defined.
+    IndexOutOfRange { attribute: &'static str },
+    /// A model has no triangles to draw, `name` is empty for files without any model.
+    EmptyMesh { name: String },
+}
 
-pub type Obj = Vec<Vertex>;
+impl Display for ObjError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ObjError::Io { path, error } => {
+                write!(f, "Couldn't access OBJ `{}`: {error}", path.display())
+            }
+            ObjError::Parse { error } => write!(f, "Malformed OBJ: {error}"),
+            ObjError::IndexOutOfRange { attribute } => {
+                write!(f, "A face uses a {attribute} index that is out of range")
+            }
+            ObjError::EmptyMesh { name } if name.is_empty() => write!(f, "The OBJ has no models"),
+            ObjError::EmptyMesh { name } => write!(f, "The model `{name}` has no faces"),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
 
-pub fn load_objs(filename: &str) -> Result<Vec<Obj>, tobj::LoadError> {
-    let (models, _) = tobj::load_obj(
-        filename,
+impl From<tobj::LoadError> for ObjError {
+    fn from(error: tobj::LoadError) -> Self {
+        let attribute = match error {
+            tobj::LoadError::FaceVertexOutOfBounds => "position",
+            tobj::LoadError::FaceTexCoordOutOfBounds => "texture coordinate",
+            tobj::LoadError::FaceNormalOutOfBounds => "normal",
+            tobj::LoadError::FaceColorOutOfBounds => "color",
+            error => return ObjError::Parse { error },
+        };
+
+        ObjError::IndexOutOfRange { attribute }
+    }
+}
+
+/// Loads every model of an OBJ file, along the materials of the MTL files it references.
+/// A missing or broken MTL file leaves the meshes without a material.
+///
+/// Models without normals get smooth normals, see `load_objs_with_normals`.
+pub fn load_objs(filename: impl AsRef<Path>) -> Result<Vec<Mesh>, ObjError> {
+    load_objs_with_normals(filename, NormalStrategy::default())
+}
+
+/// Same as `load_objs`, but the normals of models without them are generated with the given
+/// `strategy`. Each mesh reports the strategy in `Mesh::generated_normals`.
+pub fn load_objs_with_normals(
+    filename: impl AsRef<Path>,
+    strategy: NormalStrategy,
+) -> Result<Vec<Mesh>, ObjError> {
+    let path = filename.as_ref();
+    let source = fs::read(path).map_err(|error| ObjError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+
+    parse_objs(&source, base_dir, strategy)
+}
+
+/// Parses the models of the OBJ file in `source`.
+///
+/// * `base_dir`: The directory the paths of MTL files are relative to.
+/// * `strategy`: How to generate the normals of models without them.
+pub fn parse_objs(
+    source: &[u8],
+    base_dir: &Path,
+    strategy: NormalStrategy,
+) -> Result<Vec<Mesh>, ObjError> {
+    let (models, materials) = tobj::load_obj_buf(
+        &mut &source[..],
         &tobj::LoadOptions {
             single_index: true,
             triangulate: true,
             ..Default::default()
         },
+        |mtl_path| tobj::load_mtl(base_dir.join(mtl_path)),
     )?;
+    if models.is_empty() {
+        return Err(ObjError::EmptyMesh {
+            name: String::new(),
+        });
+    }
+    let materials: Vec<Material> = materials
+        .unwrap_or_default()
+        .iter()
+        .map(Material::from)
+        .collect();
 
-    let objs = models
+    models
         .into_iter()
-        .map(|m| m.mesh)
-        .map(|mesh| {
+        .map(|tobj::Model { mesh, name }| {
+            if mesh.indices.len() < 3 {
+                return Err(ObjError::EmptyMesh { name });
+            }
+            let vertex_count = mesh.positions.len() / 3;
+            if mesh.indices.iter().any(|&idx| idx as usize >= vertex_count) {
+                return Err(ObjError::IndexOutOfRange {
+                    attribute: "position",
+                });
+            }
+
             let vertices: Vec<Vec3> = mesh
                 .positions
                 .chunks(3)
@@ -37,25 +198,261 @@ pub fn load_objs(filename: &str) -> Result<Vec<Obj>, tobj::LoadError> {
                 .map(|t| Vec2::new(t[0], t[1]))
                 .collect();
 
-            let indices: Vec<usize> = mesh.indices.iter().map(|idx| *idx as usize).collect();
-            get_vertex_array(indices, vertices, normals, texcoords)
+            let has_normals = normals.len() == vertices.len();
+            let mut obj = Mesh {
+                material: mesh.material_id.and_then(|id| materials.get(id)).copied(),
+                ..Mesh::new(get_vertex_array(vertices, normals, texcoords), mesh.indices)
+            };
+            if !has_normals {
+                generate_normals(&mut obj, strategy);
+            }
+
+            Ok(obj)
         })
-        .collect();
+        .collect()
+}
+
+/// Saves the `meshes` into an OBJ file at `path`, one object for each mesh.
+pub fn save_objs(path: impl AsRef<Path>, meshes: &[Mesh]) -> Result<(), ObjError> {
+    let path = path.as_ref();
+    let io_error = |error| ObjError::Io {
+        path: path.to_path_buf(),
+        error,
+    };
+
+    let mut writer = BufWriter::new(fs::File::create(path).map_err(io_error)?);
+    write_objs(&mut writer, meshes).map_err(io_error)?;
+    writer.flush().map_err(io_error)
+}
+
+/// Writes the `meshes` in the OBJ format, one object for each mesh.
+///
+/// Every vertex writes its position, texture coordinates and normal, so faces use the same index
+/// for all of them. Numbers are written with all the digits they need to be read back exactly.
+pub fn write_objs(writer: &mut impl Write, meshes: &[Mesh]) -> io::Result<()> {
+    // OBJ indices start at 1 and keep counting across objects.
+    let mut offset = 1;
+
+    for (idx, mesh) in meshes.iter().enumerate() {
+        writeln!(writer, "o mesh_{idx}")?;
+        for Vertex { position: p, .. } in &mesh.vertices {
+            writeln!(writer, "v {} {} {}", p.x, p.y, p.z)?;
+        }
+        for Vertex { tex_coords: t, .. } in &mesh.vertices {
+            writeln!(writer, "vt {} {}", t.x, t.y)?;
+        }
+        for Vertex { normal: n, .. } in &mesh.vertices {
+            writeln!(writer, "vn {} {} {}", n.x, n.y, n.z)?;
+        }
+        for triangle in mesh.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| triangle[i] as usize + offset);
+            writeln!(writer, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}")?;
+        }
+
+        offset += mesh.vertices.len();
+    }
+
+    Ok(())
+}
+
+/// Builds the vertex buffer of a mesh, loaded with a single index for every attribute.
+fn get_vertex_array(vertices: Vec<Vec3>, normals: Vec<Vec3>, texcoords: Vec<Vec2>) -> Vec<Vertex> {
+    vertices
+        .into_iter()
+        .enumerate()
+        .map(|(idx, position)| {
+            let normal = *normals.get(idx).unwrap_or(&Vec3::zeros());
+            let tex_cords = *texcoords.get(idx).unwrap_or(&Vec2::new(0.0, 0.0));
+            Vertex::new(position, normal, tex_cords)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color::Color,
+        primitives::{icosphere, uv_sphere},
+        test_utils::temp_path,
+    };
+
+    #[test]
+    fn test_shared_vertices_are_stored_once() {
+        let meshes = load_objs("cube.obj").unwrap();
+        let mesh = &meshes[0];
+
+        assert_eq!(mesh.triangle_count(), 12);
+        assert!(mesh.vertices.len() < mesh.indices.len());
+        assert!(mesh
+            .indices
+            .iter()
+            .all(|&idx| (idx as usize) < mesh.vertices.len()));
+    }
+
+    #[test]
+    fn test_meshes_get_their_mtl_material() {
+        let cube = &load_objs("cube.obj").unwrap()[0];
+        let sphere = &load_objs("sphere.obj").unwrap()[0];
+
+        let material = cube.material.unwrap();
+        assert_eq!(material.diffuse, Color::new(204, 204, 204));
+        assert_eq!(material.specular, 250.0);
+        assert_eq!(material.refractive_index, 1.45);
+        // The sphere references its MTL file, but never uses a material from it.
+        assert!(sphere.material.is_none());
+    }
+
+    fn write_obj(name: &str, source: &str) -> std::path::PathBuf {
+        let path = temp_path(&format!("{name}.obj"));
+        std::fs::write(&path, source).unwrap();
+
+        path
+    }
+
+    /// A square pyramid without normals, its sides are steeper than the default crease angle.
+    const PYRAMID: &str = "
+        v -1 0 -1
+        v 1 0 -1
+        v 1 0 1
+        v -1 0 1
+        v 0 2 0
+        f 1 2 3 4
+        f 4 3 5
+        f 3 2 5
+        f 2 1 5
+        f 1 4 5
+    ";
+
+    #[test]
+    fn test_missing_normals_are_generated() {
+        let path = write_obj("pyramid", PYRAMID);
+
+        let smooth = &load_objs(&path).unwrap()[0];
+        let flat = &load_objs_with_normals(&path, NormalStrategy::Flat).unwrap()[0];
+        let loaded = &load_objs("cube.obj").unwrap()[0];
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(smooth.generated_normals, Some(NormalStrategy::default()));
+        assert_eq!(flat.generated_normals, Some(NormalStrategy::Flat));
+        assert_eq!(loaded.generated_normals, None);
+        for mesh in [smooth, flat] {
+            for vertex in &mesh.vertices {
+                assert!((vertex.normal.magnitude() - 1.0).abs() < 1e-5);
+            }
+        }
+        // Every face of the flat pyramid has its own vertices.
+        assert_eq!(flat.vertices.len(), 4 + 4 * 3);
+        // The base is flat and faces down.
+        for vertex in smooth.vertices.iter().filter(|v| v.normal.y < -0.5) {
+            assert!((vertex.normal - Vec3::new(0.0, -1.0, 0.0)).magnitude() < 1e-5);
+        }
+    }
+
+    fn parse(source: &str) -> Result<Vec<Mesh>, ObjError> {
+        parse_objs(source.as_bytes(), Path::new(""), NormalStrategy::default())
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let error = load_objs("does_not_exist.obj").unwrap_err();
+
+        assert!(
+            matches!(error, ObjError::Io { path, .. } if path == Path::new("does_not_exist.obj"))
+        );
+    }
+
+    #[test]
+    fn test_malformed_statements() {
+        for source in [
+            "v 0 0 zero\nv 1 0 0\nv 0 1 0\nf 1 2 3",
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0.5\nf 1/1 2/1 3/1",
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 three",
+        ] {
+            let error = parse(source).unwrap_err();
+            assert!(matches!(error, ObjError::Parse { .. }), "{source}: {error}");
+        }
+    }
+
+    #[test]
+    fn test_out_of_range_indices() {
+        let cases = [
+            ("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4", "position"),
+            ("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 -4", "position"),
+            (
+                "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\nf 1//1 2//1 3//2",
+                "normal",
+            ),
+            (
+                "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nf 1/1 2/1 3/2",
+                "texture coordinate",
+            ),
+        ];
+
+        for (source, expected) in cases {
+            let error = parse(source).unwrap_err();
+            assert!(
+                matches!(error, ObjError::IndexOutOfRange { attribute } if attribute == expected),
+                "{source}: {error}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_empty_meshes() {
+        assert!(matches!(
+            parse("# Nothing to see here").unwrap_err(),
+            ObjError::EmptyMesh { .. }
+        ));
+        assert!(matches!(
+            parse("o points\nv 0 0 0\nv 1 0 0\nv 0 1 0\np 1 2 3").unwrap_err(),
+            ObjError::EmptyMesh { .. }
+        ));
+        assert_eq!(
+            parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3").unwrap()[0].triangle_count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let meshes = [icosphere(2), uv_sphere(16, 8)];
+        let path = temp_path("round_trip.obj");
+
+        save_objs(&path, &meshes).unwrap();
+        let loaded = load_objs(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(loaded.len(), meshes.len());
+        for (loaded, mesh) in loaded.iter().zip(&meshes) {
+            // Vertices no triangle uses, like some on the poles of the UV sphere, are dropped.
+            let used: std::collections::HashSet<_> = mesh.indices.iter().collect();
+            assert_eq!(loaded.vertices.len(), used.len());
+            assert_eq!(loaded.generated_normals, None);
+            for (loaded, vertex) in loaded
+                .triangles(&loaded.vertices)
+                .flatten()
+                .zip(mesh.triangles(&mesh.vertices).flatten())
+            {
+                assert_eq!(loaded.position, vertex.position);
+                assert_eq!(loaded.normal, vertex.normal);
+                assert_eq!(loaded.tex_coords, vertex.tex_coords);
+            }
+        }
+    }
+
+    #[test]
+    fn test_triangles_follow_indices() {
+        let vertices = (0..4)
+            .map(|i| Vertex::new(Vec3::new(i as f32, 0.0, 0.0), Vec3::zeros(), Vec2::zeros()))
+            .collect();
+        let mesh = Mesh::new(vertices, vec![0, 1, 2, 2, 1, 3]);
+
+        let triangles: Vec<[f32; 3]> = mesh
+            .triangles(&mesh.vertices)
+            .map(|triangle| triangle.map(|v| v.position.x))
+            .collect();
 
-    Ok(objs)
-}
-
-fn get_vertex_array(
-    indices: Vec<usize>,
-    vertices: Vec<Vec3>,
-    normals: Vec<Vec3>,
-    texcoords: Vec<Vec2>,
-) -> Vec<Vertex> {
-    (indices.iter().map(|idx| {
-        let position = *vertices.get(*idx).unwrap();
-        let normal = *normals.get(*idx).unwrap_or(&Vec3::new(0.4, 0.3, 0.3));
-        let tex_cords = *texcoords.get(*idx).unwrap_or(&Vec2::new(0.0, 0.0));
-        Vertex::new(position, normal, tex_cords)
-    }))
-    .collect()
+        assert_eq!(triangles, vec![[0.0, 1.0, 2.0], [2.0, 1.0, 3.0]]);
+    }
 }
@@ -0,0 +1,641 @@
+//! Meshes of PLY files, in ASCII or binary (little and big endian) format.
+//!
+//! The `vertex` element gives the positions (`x`, `y`, `z`), and optionally the normals (`nx`,
+//! `ny`, `nz`), texture coordinates (`u`, `v` or `s`, `t`) and colors (`red`, `green`, `blue`).
+//! The `face` element gives polygons, which are split into triangles. Any other element or
+//! property is skipped. Meshes are saved as binary little endian PLY, with every attribute.
+
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    fs,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use nalgebra_glm::{vec2, vec3, Vec2, Vec3};
+
+use crate::{
+    color::Color,
+    normals::{generate_normals, NormalStrategy},
+    obj::Mesh,
+    vertex::Vertex,
+};
+
+#[derive(Debug)]
+pub enum PlyError {
+    /// The PLY file couldn't be read.
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    /// The header is malformed or uses something that isn't supported.
+    Header { line: usize, message: String },
+    /// The data after the header doesn't match what the header describes.
+    Body { message: String },
+    /// A face uses a vertex that doesn't exist.
+    IndexOutOfRange { face: usize },
+    /// The file has no faces.
+    EmptyMesh,
+}
+
+impl Display for PlyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlyError::Io { path, error } => {
+                write!(f, "Couldn't read PLY `{}`: {error}", path.display())
+            }
+            PlyError::Header { line, message } => {
+                write!(f, "Malformed PLY header at line {line}: {message}")
+            }
+            PlyError::Body { message } => write!(f, "Malformed PLY: {message}"),
+            PlyError::IndexOutOfRange { face } => {
+                write!(f, "The face {face} uses a vertex that is out of range")
+            }
+            PlyError::EmptyMesh => write!(f, "The PLY has no faces"),
+        }
+    }
+}
+
+impl std::error::Error for PlyError {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn from_name(name: &str) -> Option<Self> {
+        let scalar = match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return None,
+        };
+
+        Some(scalar)
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum PropertyKind {
+    Scalar(Scalar),
+    List { count: Scalar, item: Scalar },
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<(String, PropertyKind)>,
+}
+
+/// Loads the mesh of the PLY file at `path`.
+pub fn load_ply(path: impl AsRef<Path>) -> Result<Mesh, PlyError> {
+    let path = path.as_ref();
+    let source = fs::read(path).map_err(|error| PlyError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+
+    parse_ply(&source)
+}
+
+/// Parses a PLY file. Meshes without normals get smooth normals.
+pub fn parse_ply(source: &[u8]) -> Result<Mesh, PlyError> {
+    let (format, elements, body) = parse_header(source)?;
+    let mut values = Values::new(format, body);
+
+    let vertex_count = elements
+        .iter()
+        .find(|element| element.name == "vertex")
+        .map_or(0, |element| element.count);
+
+    let mut vertices = vec![];
+    let mut has_normals = false;
+    let mut indices = vec![];
+    for element in &elements {
+        match element.name.as_str() {
+            "vertex" => {
+                has_normals = ["nx", "ny", "nz"]
+                    .iter()
+                    .all(|name| element.properties.iter().any(|(p, _)| p == name));
+                vertices = read_vertices(element, &mut values)?;
+            }
+            "face" => indices = read_faces(element, vertex_count, &mut values)?,
+            _ => {
+                for _ in 0..element.count {
+                    read_properties(element, &mut values)?;
+                }
+            }
+        }
+    }
+
+    if indices.is_empty() {
+        return Err(PlyError::EmptyMesh);
+    }
+
+    let mut mesh = Mesh::new(vertices, indices);
+    if !has_normals {
+        generate_normals(&mut mesh, NormalStrategy::default());
+    }
+
+    Ok(mesh)
+}
+
+/// Saves the `mesh` into a binary PLY file at `path`.
+pub fn save_ply(path: impl AsRef<Path>, mesh: &Mesh) -> Result<(), PlyError> {
+    let path = path.as_ref();
+    let io_error = |error| PlyError::Io {
+        path: path.to_path_buf(),
+        error,
+    };
+
+    let mut writer = BufWriter::new(fs::File::create(path).map_err(io_error)?);
+    write_ply(&mut writer, mesh).map_err(io_error)?;
+    writer.flush().map_err(io_error)
+}
+
+/// Writes the `mesh` in the binary little endian PLY format, with the position, normal, texture
+/// coordinates and color of every vertex and a triangle for every face.
+pub fn write_ply(writer: &mut impl Write, mesh: &Mesh) -> io::Result<()> {
+    write!(
+        writer,
+        "ply\n\
+         format binary_little_endian 1.0\n\
+         element vertex {}\n\
+         property float x\nproperty float y\nproperty float z\n\
+         property float nx\nproperty float ny\nproperty float nz\n\
+         property float u\nproperty float v\n\
+         property uchar red\nproperty uchar green\nproperty uchar blue\n\
+         element face {}\n\
+         property list uchar uint vertex_indices\n\
+         end_header\n",
+        mesh.vertices.len(),
+        mesh.triangle_count()
+    )?;
+
+    for Vertex {
+        position: p,
+        normal: n,
+        tex_coords: t,
+        color,
+        ..
+    } in &mesh.vertices
+    {
+        for value in [p.x, p.y, p.z, n.x, n.y, n.z, t.x, t.y] {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        writer.write_all(&[color.r, color.g, color.b])?;
+    }
+    for triangle in mesh.indices.chunks_exact(3) {
+        writer.write_all(&[3])?;
+        for idx in triangle {
+            writer.write_all(&idx.to_le_bytes())?;
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_header(source: &[u8]) -> Result<(Format, Vec<Element>, &[u8]), PlyError> {
+    let mut format = None;
+    let mut elements: Vec<Element> = vec![];
+    let mut rest = source;
+
+    for line_number in 1.. {
+        let error = |message: &str| PlyError::Header {
+            line: line_number,
+            message: message.to_string(),
+        };
+        let end = rest
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or_else(|| error("The header never ends"))?;
+        let line = std::str::from_utf8(&rest[..end]).map_err(|_| error("It isn't text"))?;
+        rest = &rest[end + 1..];
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words[..] {
+            ["ply"] if line_number == 1 => {}
+            _ if line_number == 1 => return Err(error("PLY files start with `ply`")),
+            ["format", name, _version] => {
+                format = Some(match name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => return Err(error("Unknown format")),
+                });
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| error("Invalid element count"))?,
+                properties: vec![],
+            }),
+            ["property", "list", count, item, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| error("A property must belong to an element"))?;
+                let (count, item) = Scalar::from_name(count)
+                    .zip(Scalar::from_name(item))
+                    .ok_or_else(|| error("Unknown property type"))?;
+                element
+                    .properties
+                    .push((name.to_string(), PropertyKind::List { count, item }));
+            }
+            ["property", kind, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| error("A property must belong to an element"))?;
+                let scalar =
+                    Scalar::from_name(kind).ok_or_else(|| error("Unknown property type"))?;
+                element
+                    .properties
+                    .push((name.to_string(), PropertyKind::Scalar(scalar)));
+            }
+            ["end_header"] => break,
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => return Err(error("Unknown header line")),
+        }
+    }
+
+    let format = format.ok_or(PlyError::Header {
+        line: 1,
+        message: "The format is missing".to_string(),
+    })?;
+
+    Ok((format, elements, rest))
+}
+
+fn read_vertices(element: &Element, values: &mut Values) -> Result<Vec<Vertex>, PlyError> {
+    // Colors stored as floats go from 0 to 1, integer ones are bytes already.
+    let is_float = |name: &str| {
+        element.properties.iter().any(|(property, kind)| {
+            property == name && matches!(kind, PropertyKind::Scalar(Scalar::F32 | Scalar::F64))
+        })
+    };
+    let channel = |name: &str, value: f32| {
+        let value = if is_float(name) { value * 255.0 } else { value };
+        value.round().clamp(0.0, 255.0) as u8
+    };
+
+    (0..element.count)
+        .map(|_| {
+            let properties = read_properties(element, values)?;
+            let get = |names: &[&str]| {
+                names
+                    .iter()
+                    .find_map(|name| properties.get(name)?.first().map(|&v| v as f32))
+            };
+            let axis = |name| {
+                get(&[name]).ok_or_else(|| PlyError::Body {
+                    message: format!("A vertex has no `{name}`"),
+                })
+            };
+
+            let position = vec3(axis("x")?, axis("y")?, axis("z")?);
+            let normal = match (get(&["nx"]), get(&["ny"]), get(&["nz"])) {
+                (Some(x), Some(y), Some(z)) => vec3(x, y, z),
+                _ => Vec3::zeros(),
+            };
+            let tex_coords = match (
+                get(&["u", "s", "texture_u", "texture_s"]),
+                get(&["v", "t", "texture_v", "texture_t"]),
+            ) {
+                (Some(u), Some(v)) => vec2(u, v),
+                _ => Vec2::zeros(),
+            };
+
+            let mut vertex = Vertex::new(position, normal, tex_coords);
+            if let (Some(r), Some(g), Some(b)) = (get(&["red"]), get(&["green"]), get(&["blue"])) {
+                vertex.color =
+                    Color::new(channel("red", r), channel("green", g), channel("blue", b));
+            }
+            Ok(vertex)
+        })
+        .collect()
+}
+
+/// Reads the faces of the `element` as triangles, checking that they only use the first
+/// `vertex_count` vertices.
+fn read_faces(
+    element: &Element,
+    vertex_count: usize,
+    values: &mut Values,
+) -> Result<Vec<u32>, PlyError> {
+    let mut indices = vec![];
+
+    for face in 0..element.count {
+        let properties = read_properties(element, values)?;
+        let polygon = properties
+            .get("vertex_indices")
+            .or_else(|| properties.get("vertex_index"))
+            .ok_or_else(|| PlyError::Body {
+                message: "A face has no `vertex_indices`".to_string(),
+            })?;
+
+        // Indices may be stored as any type, casting negative or fractional ones would pick
+        // another vertex. Errors count the faces of the file, not the triangles of the fan.
+        let polygon: Vec<u32> = polygon
+            .iter()
+            .map(|&idx| {
+                if idx >= 0.0 && idx.fract() == 0.0 && idx < vertex_count as f64 {
+                    Ok(idx as u32)
+                } else {
+                    Err(PlyError::IndexOutOfRange { face })
+                }
+            })
+            .collect::<Result<_, _>>()?;
+
+        // Polygons are split into a fan of triangles.
+        for i in 1..polygon.len().saturating_sub(1) {
+            indices.extend([polygon[0], polygon[i], polygon[i + 1]]);
+        }
+    }
+
+    Ok(indices)
+}
+
+/// Reads every property of one item of the `element`, lists and scalars alike.
+fn read_properties<'a>(
+    element: &'a Element,
+    values: &mut Values,
+) -> Result<HashMap<&'a str, Vec<f64>>, PlyError> {
+    element
+        .properties
+        .iter()
+        .map(|(name, kind)| {
+            let value = match kind {
+                PropertyKind::Scalar(scalar) => vec![values.next(*scalar)?],
+                PropertyKind::List { count, item } => {
+                    let count = values.next(*count)? as usize;
+                    (0..count)
+                        .map(|_| values.next(*item))
+                        .collect::<Result<_, _>>()?
+                }
+            };
+            Ok((name.as_str(), value))
+        })
+        .collect()
+}
+
+/// Reads the values of the body of the file one after the other.
+struct Values<'a> {
+    format: Format,
+    body: &'a [u8],
+    words: std::str::SplitAsciiWhitespace<'a>,
+}
+
+impl<'a> Values<'a> {
+    fn new(format: Format, body: &'a [u8]) -> Self {
+        let text = match format {
+            Format::Ascii => std::str::from_utf8(body).unwrap_or_default(),
+            _ => "",
+        };
+
+        Values {
+            format,
+            body,
+            words: text.split_ascii_whitespace(),
+        }
+    }
+
+    fn next(&mut self, scalar: Scalar) -> Result<f64, PlyError> {
+        let missing = || PlyError::Body {
+            message: "The file ends before all the elements of the header".to_string(),
+        };
+
+        if self.format == Format::Ascii {
+            let word = self.words.next().ok_or_else(missing)?;
+            return word.parse().map_err(|_| PlyError::Body {
+                message: format!("`{word}` isn't a number"),
+            });
+        }
+
+        let size = scalar.size();
+        if self.body.len() < size {
+            return Err(missing());
+        }
+        let (bytes, rest) = self.body.split_at(size);
+        self.body = rest;
+
+        let mut buffer = [0; 8];
+        buffer[..size].copy_from_slice(bytes);
+        if self.format == Format::BinaryBigEndian {
+            buffer[..size].reverse();
+        }
+
+        let value = match scalar {
+            Scalar::I8 => i8::from_le_bytes([buffer[0]]) as f64,
+            Scalar::U8 => buffer[0] as f64,
+            Scalar::I16 => i16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+            Scalar::U16 => u16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+            Scalar::I32 => i32::from_le_bytes(buffer[..4].try_into().unwrap()) as f64,
+            Scalar::U32 => u32::from_le_bytes(buffer[..4].try_into().unwrap()) as f64,
+            Scalar::F32 => f32::from_le_bytes(buffer[..4].try_into().unwrap()) as f64,
+            Scalar::F64 => f64::from_le_bytes(buffer),
+        };
+
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::uv_sphere;
+
+    /// Writes the positions, normals and faces of the mesh in the given PLY `format`.
+    fn to_ply(mesh: &Mesh, format: &str) -> Vec<u8> {
+        let mut ply = format!(
+            "ply\nformat {format} 1.0\ncomment made by the tests\n\
+             element vertex {}\nproperty float x\nproperty float y\nproperty float z\n\
+             property float nx\nproperty float ny\nproperty float nz\n\
+             element face {}\nproperty list uchar int vertex_indices\nend_header\n",
+            mesh.vertices.len(),
+            mesh.triangle_count()
+        )
+        .into_bytes();
+
+        let big_endian = format == "binary_big_endian";
+        let float = |ply: &mut Vec<u8>, value: f32| match (format, big_endian) {
+            ("ascii", _) => ply.extend(format!("{value} ").bytes()),
+            (_, true) => ply.extend(value.to_be_bytes()),
+            (_, false) => ply.extend(value.to_le_bytes()),
+        };
+        for vertex in &mesh.vertices {
+            let (p, n) = (vertex.position, vertex.normal);
+            for value in [p.x, p.y, p.z, n.x, n.y, n.z] {
+                float(&mut ply, value);
+            }
+            if format == "ascii" {
+                ply.push(b'\n');
+            }
+        }
+        for triangle in mesh.indices.chunks(3) {
+            if format == "ascii" {
+                ply.extend(format!("3 {} {} {}\n", triangle[0], triangle[1], triangle[2]).bytes());
+                continue;
+            }
+            ply.push(3);
+            for &idx in triangle {
+                let idx = idx as i32;
+                ply.extend(if big_endian {
+                    idx.to_be_bytes()
+                } else {
+                    idx.to_le_bytes()
+                });
+            }
+        }
+
+        ply
+    }
+
+    #[test]
+    fn test_formats() {
+        let sphere = uv_sphere(12, 6);
+
+        for format in ["ascii", "binary_little_endian", "binary_big_endian"] {
+            let mesh = parse_ply(&to_ply(&sphere, format)).unwrap();
+
+            assert_eq!(mesh.vertices.len(), sphere.vertices.len(), "{format}");
+            assert_eq!(mesh.indices, sphere.indices, "{format}");
+            assert_eq!(mesh.generated_normals, None);
+            for (vertex, expected) in mesh.vertices.iter().zip(&sphere.vertices) {
+                assert_eq!(vertex.position, expected.position, "{format}");
+                assert_eq!(vertex.normal, expected.normal, "{format}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_polygons_colors_and_generated_normals() {
+        let ply = "ply
+format ascii 1.0
+element vertex 4
+property double x
+property double y
+property double z
+property uchar red
+property uchar green
+property uchar blue
+element material 1
+property float shininess
+element face 1
+property list uchar uint vertex_index
+end_header
+0 0 0 255 0 0
+1 0 0 255 0 0
+1 1 0 255 0 0
+0 1 0 255 0 0
+20
+4 0 1 2 3
+";
+
+        let mesh = parse_ply(ply.as_bytes()).unwrap();
+
+        assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(mesh.generated_normals, Some(NormalStrategy::default()));
+        for vertex in &mesh.vertices {
+            assert_eq!(vertex.color, Color::red());
+            assert_eq!(vertex.normal, vec3(0.0, 0.0, 1.0));
+        }
+
+        // Float colors go from 0 to 1.
+        let float_colors = ply
+            .replace("uchar red", "float red")
+            .replace("uchar green", "float green")
+            .replace("uchar blue", "float blue")
+            .replace(" 255 0 0\n", " 1.0 0.5 0.0\n");
+        let mesh = parse_ply(float_colors.as_bytes()).unwrap();
+        for vertex in &mesh.vertices {
+            assert_eq!(vertex.color, Color::new(255, 128, 0));
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut sphere = uv_sphere(16, 8);
+        for (idx, vertex) in sphere.vertices.iter_mut().enumerate() {
+            vertex.color = Color::from(idx as u32 * 0x010305);
+        }
+        let path = crate::test_utils::temp_path("round_trip.ply");
+
+        save_ply(&path, &sphere).unwrap();
+        let loaded = load_ply(&path);
+        fs::remove_file(path).unwrap();
+        let loaded = loaded.unwrap();
+
+        assert_eq!(loaded.indices, sphere.indices);
+        assert_eq!(loaded.generated_normals, None);
+        assert_eq!(loaded.vertices.len(), sphere.vertices.len());
+        for (loaded, vertex) in loaded.vertices.iter().zip(&sphere.vertices) {
+            assert_eq!(loaded.position, vertex.position);
+            assert_eq!(loaded.normal, vertex.normal);
+            assert_eq!(loaded.tex_coords, vertex.tex_coords);
+            assert_eq!(loaded.color, vertex.color);
+        }
+    }
+
+    #[test]
+    fn test_malformed() {
+        let header = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\n\
+                      property float y\nproperty float z\nelement face 1\n\
+                      property list uchar int vertex_indices\nend_header\n";
+
+        assert!(matches!(
+            parse_ply(b"plyx\n"),
+            Err(PlyError::Header { line: 1, .. })
+        ));
+        assert!(matches!(
+            parse_ply(b"ply\nformat ascii 1.0\nproperty float x\nend_header\n"),
+            Err(PlyError::Header { line: 3, .. })
+        ));
+        assert!(matches!(
+            parse_ply(format!("{header}0 0 0\n1 0 0\n").as_bytes()),
+            Err(PlyError::Body { .. })
+        ));
+        assert!(matches!(
+            parse_ply(format!("{header}0 0 0\n1 0 0\n0 1 0\n3 0 1 3\n").as_bytes()),
+            Err(PlyError::IndexOutOfRange { face: 0 })
+        ));
+        let two_faces = header.replace("element face 1", "element face 2");
+        assert!(matches!(
+            parse_ply(format!("{two_faces}0 0 0\n1 0 0\n0 1 0\n4 0 1 2 0\n3 0 1 3\n").as_bytes()),
+            Err(PlyError::IndexOutOfRange { face: 1 })
+        ));
+        assert!(matches!(
+            parse_ply(format!("{header}0 0 0\n1 0 0\n0 1 0\n3 0 1 -1\n").as_bytes()),
+            Err(PlyError::IndexOutOfRange { face: 0 })
+        ));
+        assert!(parse_ply(format!("{header}0 0 0\n1 0 0\n0 1 0\n3 0 1 2\n").as_bytes()).is_ok());
+    }
+}
@@ -0,0 +1,240 @@
+use std::{
+    collections::HashMap,
+    f32::consts::{PI, TAU},
+};
+
+use nalgebra_glm::{vec2, vec3, Vec2, Vec3};
+
+use crate::{obj::Mesh, vertex::Vertex};
+
+/// Creates a sphere of radius 1 made of `segments` slices around the Y axis and `rings` stacked
+/// from the south to the north pole.
+///
+/// The texture coordinates wrap once around the sphere along U, and go from the south (V = 0) to
+/// the north pole (V = 1). The vertices on the seam are duplicated so U never jumps back to 0.
+pub fn uv_sphere(segments: usize, rings: usize) -> Mesh {
+    let segments = segments.max(3);
+    let rings = rings.max(2);
+
+    let mut vertices = Vec::with_capacity((segments + 1) * (rings + 1));
+    for ring in 0..=rings {
+        for segment in 0..=segments {
+            let u = segment as f32 / segments as f32;
+            let v = ring as f32 / rings as f32;
+            let (sin_theta, cos_theta) = (u * TAU).sin_cos();
+            let latitude = v * PI - PI / 2.0;
+            let (sin_phi, cos_phi) = latitude.sin_cos();
+            let position = vec3(cos_phi * cos_theta, sin_phi, -cos_phi * sin_theta);
+
+            vertices.push(Vertex::new(position, position, vec2(u, v)));
+        }
+    }
+
+    let index = |segment: usize, ring: usize| (ring * (segments + 1) + segment) as u32;
+    let mut indices = Vec::with_capacity(segments * (rings - 1) * 6);
+    for ring in 0..rings {
+        for segment in 0..segments {
+            let bottom_left = index(segment, ring);
+            let bottom_right = index(segment + 1, ring);
+            let top_left = index(segment, ring + 1);
+            let top_right = index(segment + 1, ring + 1);
+
+            // The rings touching the poles would have a degenerated triangle.
+            if ring != 0 {
+                indices.extend([bottom_left, bottom_right, top_right]);
+            }
+            if ring != rings - 1 {
+                indices.extend([bottom_left, top_right, top_left]);
+            }
+        }
+    }
+
+    Mesh::new(vertices, indices)
+}
+
+/// Creates a sphere of radius 1 by splitting every triangle of an icosahedron in 4,
+/// `subdivisions` times. Unlike a UV sphere, its triangles are all about the same size.
+///
+/// The texture coordinates are mapped like the ones of `uv_sphere`.
+pub fn icosphere(subdivisions: usize) -> Mesh {
+    let (mut positions, mut faces) = icosahedron();
+
+    for _ in 0..subdivisions {
+        let mut midpoints = HashMap::new();
+        let mut midpoint = |a: usize, b: usize| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                positions.push(((positions[a] + positions[b]) / 2.0).normalize());
+                positions.len() - 1
+            })
+        };
+
+        faces = faces
+            .into_iter()
+            .flat_map(|[a, b, c]| {
+                let ab = midpoint(a, b);
+                let bc = midpoint(b, c);
+                let ca = midpoint(c, a);
+
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    // Vertices are shared between faces, unless they are on the seam or a pole, where each face
+    // needs its own texture coordinates.
+    let mut mesh = Mesh::default();
+    let mut shared = HashMap::new();
+    for face in faces {
+        let tex_coords = face_tex_coords(&face.map(|idx| positions[idx]));
+
+        for (idx, uv) in face.into_iter().zip(tex_coords) {
+            let key = (idx, uv.x.to_bits(), uv.y.to_bits());
+            let vertex = *shared.entry(key).or_insert_with(|| {
+                let position = positions[idx];
+                mesh.vertices.push(Vertex::new(position, position, uv));
+                mesh.vertices.len() as u32 - 1
+            });
+            mesh.indices.push(vertex);
+        }
+    }
+
+    mesh
+}
+
+/// The vertices and faces of an icosahedron with its vertices on the unit sphere.
+/// Faces are wound counter-clockwise when seen from outside.
+fn icosahedron() -> (Vec<Vec3>, Vec<[usize; 3]>) {
+    let t = (1.0 + 5.0_f32.sqrt()) / 2.0;
+
+    #[rustfmt::skip]
+    let positions = [
+        vec3(-1.0, t, 0.0),     vec3(1.0, t, 0.0),      vec3(-1.0, -t, 0.0),    vec3(1.0, -t, 0.0),
+        vec3(0.0, -1.0, t),     vec3(0.0, 1.0, t),      vec3(0.0, -1.0, -t),    vec3(0.0, 1.0, -t),
+        vec3(t, 0.0, -1.0),     vec3(t, 0.0, 1.0),      vec3(-t, 0.0, -1.0),    vec3(-t, 0.0, 1.0),
+    ];
+
+    #[rustfmt::skip]
+    let faces = vec![
+        [0, 11, 5],     [0, 5, 1],      [0, 1, 7],      [0, 7, 10],     [0, 10, 11],
+        [1, 5, 9],      [5, 11, 4],     [11, 10, 2],    [10, 7, 6],     [7, 1, 8],
+        [3, 9, 4],      [3, 4, 2],      [3, 2, 6],      [3, 6, 8],      [3, 8, 9],
+        [4, 9, 5],      [2, 4, 11],     [6, 2, 10],     [8, 6, 7],      [9, 8, 1],
+    ];
+
+    (positions.iter().map(|p| p.normalize()).collect(), faces)
+}
+
+/// Maps the vertices of a face of a sphere into texture coordinates.
+///
+/// Faces crossing the seam get their U shifted past 1, so they don't stretch across the whole
+/// texture, and vertices on a pole take the U of the rest of the face, as any U is valid there.
+fn face_tex_coords(face: &[Vec3; 3]) -> [Vec2; 3] {
+    let mut tex_coords = face.map(|p| {
+        let u = (-p.z).atan2(p.x) / TAU;
+        let v = p.y.clamp(-1.0, 1.0).asin() / PI + 0.5;
+
+        vec2(u.rem_euclid(1.0), v)
+    });
+
+    let max_u = tex_coords.iter().map(|uv| uv.x).fold(f32::MIN, f32::max);
+    for uv in &mut tex_coords {
+        if max_u - uv.x > 0.5 {
+            uv.x += 1.0;
+        }
+    }
+
+    let is_pole = face.map(|p| p.y.abs() > 1.0 - 1e-6);
+    if is_pole.iter().any(|pole| *pole) {
+        let others: Vec<f32> = (0..3)
+            .filter(|i| !is_pole[*i])
+            .map(|i| tex_coords[i].x)
+            .collect();
+        let u = others.iter().sum::<f32>() / others.len() as f32;
+        for i in (0..3).filter(|i| is_pole[*i]) {
+            tex_coords[i].x = u;
+        }
+    }
+
+    tex_coords
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra_glm::{cross, dot};
+
+    fn assert_is_unit_sphere(mesh: &Mesh) {
+        assert_eq!(mesh.indices.len() % 3, 0);
+
+        let mut volume = 0.0;
+        for triangle in mesh.triangles(&mesh.vertices) {
+            for vertex in triangle {
+                assert!((vertex.position.magnitude() - 1.0).abs() < 1e-5);
+                assert!((vertex.normal - vertex.position).magnitude() < 1e-5);
+                assert!((0.0..=2.0).contains(&vertex.tex_coords.x));
+                assert!((0.0..=1.0).contains(&vertex.tex_coords.y));
+            }
+
+            let [a, b, c] = [0, 1, 2].map(|i| triangle[i].position);
+            // Every triangle faces outwards.
+            let face_normal = cross(&(b - a), &(c - a));
+            assert!(dot(&face_normal, &(a + b + c)) > 0.0);
+            // And no triangle stretches across the whole texture.
+            let us = [0, 1, 2].map(|i| triangle[i].tex_coords.x);
+            let u_span = us.iter().fold(f32::MIN, |a, &b| a.max(b))
+                - us.iter().fold(f32::MAX, |a, &b| a.min(b));
+            assert!(u_span < 0.5, "{us:?}");
+
+            volume += dot(&a, &cross(&b, &c)) / 6.0;
+        }
+
+        // A closed surface encloses (almost) the volume of the sphere.
+        let sphere_volume = 4.0 / 3.0 * PI;
+        assert!(volume < sphere_volume && volume > sphere_volume * 0.95);
+    }
+
+    #[test]
+    fn test_uv_sphere() {
+        let sphere = uv_sphere(32, 16);
+
+        assert_eq!(sphere.triangle_count(), 32 * (16 - 1) * 2);
+        assert_eq!(sphere.vertices.len(), (32 + 1) * (16 + 1));
+        assert_is_unit_sphere(&sphere);
+    }
+
+    #[test]
+    fn test_uv_sphere_tex_coords_follow_latitude() {
+        for vertex in uv_sphere(8, 4).vertices {
+            let latitude = vertex.position.y.asin();
+            assert!((vertex.tex_coords.y - (latitude / PI + 0.5)).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_icosphere() {
+        let icosahedron = icosphere(0);
+        let sphere = icosphere(3);
+
+        assert_eq!(icosahedron.triangle_count(), 20);
+        assert_eq!(sphere.triangle_count(), 20 * 4usize.pow(3));
+        // Most vertices are shared by 6 triangles.
+        assert!(sphere.vertices.len() < sphere.indices.len() / 5);
+        assert_is_unit_sphere(&sphere);
+    }
+
+    #[test]
+    fn test_icosphere_matches_uv_sphere_mapping() {
+        for vertex in icosphere(2).vertices {
+            let p = vertex.position;
+            assert!((vertex.tex_coords.y - (p.y.asin() / PI + 0.5)).abs() < 1e-5);
+            // Any U is valid on the poles.
+            if p.y.abs() > 1.0 - 1e-6 {
+                continue;
+            }
+
+            // U wraps around, so it only has to match up to whole turns.
+            let u_error = (vertex.tex_coords.x - (-p.z).atan2(p.x) / TAU).rem_euclid(1.0);
+            assert!(u_error.min(1.0 - u_error) < 1e-4);
+        }
+    }
+}
@@ -1,82 +1,473 @@
-use fastnoise_lite::FastNoiseLite;
-use nalgebra_glm::{Mat4, Vec3};
+use std::borrow::Cow;
+
+use nalgebra_glm::Mat4;
 use rayon::prelude::*;
 
 use crate::{
-    fragment::{triangle, Fragment},
+    clipping::{clip_triangle, NEAR_PLANE},
+    fragment::{pixel_bounds, rasterize_triangle, Fragment, PixelRect},
     framebuffer::Framebuffer,
-    shader::{fragment_shader, vertex_shader, Uniforms},
+    material::Material,
+    obj::Mesh,
+    shader::{create_normal_matrix, fragment_shader, Uniforms, VertexShader},
     vertex::Vertex,
-    Entity, Model,
+    Entity, EntityShader, Model,
 };
 
-pub fn render(framebuffer: &mut Framebuffer, data: &Model, noise: &mut FastNoiseLite) {
+/// The amount of rows of each tile the framebuffer is split into.
+const TILE_HEIGHT: usize = 16;
+
+/// The vertices of a triangle, either shared with other triangles or created by the clipper.
+type Triangle<'a> = [Cow<'a, Vertex>; 3];
+
+/// A triangle ready to be rasterized, along the shaders of the entity it belongs to and the
+/// material of its mesh.
+struct BinnedTriangle<'a> {
+    vertices: Triangle<'a>,
+    shaders: &'a [EntityShader],
+    material: Option<Material>,
+}
+
+pub fn render(framebuffer: &mut Framebuffer, data: &Model) {
     let Model {
+        entities,
         render_entities,
+        scene,
         uniforms,
         camera,
         ..
     } = data;
+    let Some(screen) = PixelRect::from_dimensions(framebuffer.width, framebuffer.height) else {
+        return;
+    };
+    let camera_direction = camera.direction();
 
-    for entity in render_entities {
-        let Entity {
-            objs,
-            shaders,
-            model_matrix,
-        } = entity;
-
-        for vertex_array in objs {
-            // Vertex Shader
-            // println!("Applying shaders...");
-            let new_vertices = apply_shaders(vertex_array, uniforms, model_matrix);
-            // println!("Vertex shader applied!");
-
-            // Primitive assembly
-            // println!("Assembly...");
-            let triangles = assembly(&new_vertices);
-            // println!("Assembly done!");
-
-            // Rasterization
-            // println!("Applying rasterization...");
-            let fragments = rasterize(triangles, &camera.direction());
-            // println!("Rasterization applied!");
-
-            // println!("Applying fragment shaders...");
-            let fragments = fragments
+    // Scene graph traversal
+    let world_entities: Vec<(&Entity, Mat4)> = entities
+        .iter()
+        .chain(render_entities)
+        .map(|entity| (entity, entity.model_matrix))
+        .chain(scene.entities())
+        .collect();
+
+    // Vertex Shader, run once for every unique vertex of each mesh.
+    let transformed: Vec<(Vec<Vertex>, &Mesh, &[EntityShader])> = world_entities
+        .iter()
+        .flat_map(|(entity, model_matrix)| {
+            let Entity {
+                objs,
+                vertex_shader,
+                shaders,
+                ..
+            } = entity;
+            let normal_matrix = create_normal_matrix(model_matrix);
+
+            objs.iter().map(move |mesh| {
+                let new_vertices = apply_shaders(
+                    &mesh.vertices,
+                    vertex_shader.as_ref(),
+                    uniforms,
+                    model_matrix,
+                    &normal_matrix,
+                );
+                (new_vertices, mesh, shaders.as_slice())
+            })
+        })
+        .collect();
+
+    // Primitive assembly and clipping
+    let triangles: Vec<BinnedTriangle> = transformed
+        .iter()
+        .flat_map(|(new_vertices, mesh, shaders)| {
+            clip(assembly(mesh, new_vertices), &uniforms.viewport_matrix)
                 .into_iter()
-                .map(|f| fragment_shader(f, shaders, uniforms, noise))
-                .collect();
-            // println!("Fragment shaders applied!");
-
-            // Fragment Processing
-            // println!("Painting fragments...");
-            paint_fragments(fragments, framebuffer);
-            // println!("Fragments painted!");
-        }
-    }
+                .map(|vertices| BinnedTriangle {
+                    vertices,
+                    shaders,
+                    material: mesh.material,
+                })
+        })
+        .collect();
+
+    // Binning
+    let bins = bin_triangles(&triangles, &screen);
+
+    // Rasterization, fragment shading and depth testing, each tile on its own.
+    framebuffer
+        .tiles_mut(TILE_HEIGHT)
+        .zip(bins)
+        .for_each(|(mut tile, bin)| {
+            let bounds = tile.rect();
+
+            for idx in bin {
+                let BinnedTriangle {
+                    vertices,
+                    shaders,
+                    material,
+                } = &triangles[idx];
+
+                rasterize_triangle(
+                    &vertices[0],
+                    &vertices[1],
+                    &vertices[2],
+                    &camera_direction,
+                    &bounds,
+                    |fragment| {
+                        let x = fragment.position.x as usize;
+                        let y = fragment.position.y as usize;
+
+                        // Only shade what we know is going to be seen.
+                        if tile.is_visible(x, y, fragment.depth) {
+                            let fragment = Fragment {
+                                material: *material,
+                                ..fragment
+                            };
+                            let fragment = fragment_shader(fragment, shaders, uniforms);
+                            tile.paint(x, y, fragment.color, fragment.depth);
+                        }
+                    },
+                );
+            }
+        });
 }
 
-fn apply_shaders(vertices: &[Vertex], uniforms: &Uniforms, model_matrix: &Mat4) -> Vec<Vertex> {
+fn apply_shaders(
+    vertices: &[Vertex],
+    vertex_shader: &dyn VertexShader,
+    uniforms: &Uniforms,
+    model_matrix: &Mat4,
+    normal_matrix: &Mat4,
+) -> Vec<Vertex> {
     vertices
-        .iter()
-        .map(|v| vertex_shader(v, uniforms, model_matrix))
+        .par_iter()
+        .map(|v| vertex_shader.shade(v, uniforms, model_matrix, normal_matrix))
         .collect()
 }
 
-fn assembly(vertices: &[Vertex]) -> Vec<&[Vertex]> {
-    vertices.chunks(3).collect()
+/// Builds the triangles of `mesh` out of its shaded `vertices`, following its indices.
+fn assembly<'a>(mesh: &'a Mesh, vertices: &'a [Vertex]) -> Vec<[&'a Vertex; 3]> {
+    mesh.triangles(vertices).collect()
 }
 
-fn rasterize(triangles: Vec<&[Vertex]>, camera_direction: &Vec3) -> Vec<Fragment> {
-    triangles
-        .iter()
-        .flat_map(|tri| triangle(&tri[0], &tri[1], &tri[2], camera_direction))
-        .collect()
+/// Clips the triangles against the near plane, so vertices behind the camera never reach the
+/// rasterizer. Triangles that don't need clipping are passed through untouched.
+fn clip<'a>(triangles: Vec<[&'a Vertex; 3]>, viewport_matrix: &Mat4) -> Vec<Triangle<'a>> {
+    let mut clipped_triangles = Vec::with_capacity(triangles.len());
+
+    for tri in triangles {
+        match clip_triangle(tri, &[NEAR_PLANE], viewport_matrix) {
+            None => clipped_triangles.push(tri.map(Cow::Borrowed)),
+            Some(clipped) => clipped_triangles.extend(
+                clipped
+                    .chunks_exact(3)
+                    .map(|clipped_tri| [0, 1, 2].map(|i| Cow::Owned(clipped_tri[i].clone()))),
+            ),
+        }
+    }
+
+    clipped_triangles
+}
+
+/// Assigns every triangle to the tiles its bounding box overlaps.
+/// Each bin keeps the triangles in submission order, so depth ties resolve the same way they
+/// would when painting the triangles one after the other.
+fn bin_triangles(triangles: &[BinnedTriangle], screen: &PixelRect) -> Vec<Vec<usize>> {
+    let tile_count = (screen.max_y + 1).div_ceil(TILE_HEIGHT);
+    let mut bins = vec![vec![]; tile_count];
+
+    for (idx, BinnedTriangle { vertices, .. }) in triangles.iter().enumerate() {
+        if let Some(bounds) = pixel_bounds(&vertices[0], &vertices[1], &vertices[2], screen) {
+            for bin in &mut bins[bounds.min_y / TILE_HEIGHT..=bounds.max_y / TILE_HEIGHT] {
+                bin.push(idx);
+            }
+        }
+    }
+
+    bins
 }
 
-fn paint_fragments(fragments: Vec<Fragment>, framebuffer: &mut Framebuffer) {
-    for fragment in fragments {
-        framebuffer.set_current_color(fragment.color);
-        let _ = framebuffer.paint_point(fragment.position, fragment.depth);
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use nalgebra_glm::Vec3;
+
+    use super::*;
+    use crate::{
+        assets::mesh_cache,
+        blenders::BlendMode,
+        camera::Camera,
+        color::Color,
+        fragment::triangle,
+        fragment_shaders::{BaseColor, MaterialDiffuse},
+        light::Light,
+        planets::create_ocean_planet,
+        scene::{SceneNode, Transform},
+        shader::{
+            create_model_matrix, create_projection_matrix, create_view_matrix,
+            create_viewport_matrix, vertex_shader,
+        },
+    };
+
+    fn ocean_planet_model(width: usize, height: usize) -> Model {
+        let camera = Camera::new(
+            Vec3::new(0.0, 0.0, 3.0),
+            Vec3::zeros(),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+
+        Model {
+            entities: vec![],
+            render_entities: vec![create_ocean_planet()],
+            scene: SceneNode::default(),
+            uniforms: Uniforms {
+                view_matrix: create_view_matrix(camera.eye, camera.center, camera.up),
+                projection_matrix: create_projection_matrix(width as f32, height as f32),
+                viewport_matrix: create_viewport_matrix(width as f32, height as f32),
+                time: 1000.0,
+                camera_position: camera.eye,
+                lights: vec![Light::directional(
+                    Vec3::new(0.0, -0.5, -1.0),
+                    Color::white(),
+                    1.0,
+                )],
+                ambient_intensity: 0.0,
+            },
+            rotation: Vec3::zeros(),
+            translation: Vec3::zeros(),
+            scale: 1.0,
+            camera,
+        }
+    }
+
+    #[test]
+    fn test_tiles_match_painting_fragments() {
+        let (width, height) = (120, 90);
+        let data = ocean_planet_model(width, height);
+        let Entity {
+            objs,
+            vertex_shader,
+            shaders,
+            model_matrix,
+            ..
+        } = &data.render_entities[0];
+
+        let mut tiled = Framebuffer::new(width, height);
+        render(&mut tiled, &data);
+
+        let mut painted = Framebuffer::new(width, height);
+        let vertices = apply_shaders(
+            &objs[0].vertices,
+            vertex_shader.as_ref(),
+            &data.uniforms,
+            model_matrix,
+            &create_normal_matrix(model_matrix),
+        );
+        for tri in clip(
+            assembly(&objs[0], &vertices),
+            &data.uniforms.viewport_matrix,
+        ) {
+            let camera_direction = data.camera.direction();
+            for fragment in triangle(&tri[0], &tri[1], &tri[2], &camera_direction, width, height) {
+                let fragment = fragment_shader(fragment, shaders, &data.uniforms);
+                painted.set_current_color(fragment.color);
+                painted
+                    .paint_point(fragment.position, fragment.depth)
+                    .unwrap();
+            }
+        }
+
+        assert!(tiled.buffer.iter().any(|&c| c != 0));
+        assert_eq!(tiled.buffer, painted.buffer);
+    }
+
+    fn sphere(translation: Vec3, scale: f32, color: Color) -> Entity {
+        Entity {
+            objs: mesh_cache().load("sphere.obj").unwrap(),
+            vertex_shader: Box::new(vertex_shader),
+            shaders: vec![(Box::new(BaseColor { color }), BlendMode::Replace)],
+            model_matrix: create_model_matrix(translation, scale, Vec3::zeros()),
+            orbit: None,
+        }
+    }
+
+    #[test]
+    fn test_nearest_entity_is_visible() {
+        let (width, height) = (60, 60);
+        let near = Color::red();
+        let far = Color::blue();
+
+        for swap in [false, true] {
+            let mut entities = vec![
+                sphere(Vec3::new(0.0, 0.0, 1.0), 0.15, near),
+                sphere(Vec3::new(0.0, 0.0, -2.0), 1.5, far),
+            ];
+            if swap {
+                entities.reverse();
+            }
+            let data = Model {
+                entities,
+                render_entities: vec![],
+                ..ocean_planet_model(width, height)
+            };
+
+            let mut framebuffer = Framebuffer::new(width, height);
+            render(&mut framebuffer, &data);
+
+            let center = framebuffer.buffer[height / 2 * width + width / 2];
+            let edge = framebuffer.buffer[height / 2 * width + width / 2 - 12];
+            assert_eq!(center, u32::from(&near));
+            assert_eq!(edge, u32::from(&far));
+        }
+    }
+
+    #[test]
+    fn test_mesh_material_reaches_fragments() {
+        let (width, height) = (60, 60);
+        let data = Model {
+            render_entities: vec![Entity {
+                objs: mesh_cache().load("cube.obj").unwrap(),
+                vertex_shader: Box::new(vertex_shader),
+                shaders: vec![(Box::new(MaterialDiffuse), BlendMode::Replace)],
+                model_matrix: create_model_matrix(Vec3::zeros(), 0.5, Vec3::new(0.4, 0.6, 0.0)),
+                orbit: None,
+            }],
+            ..ocean_planet_model(width, height)
+        };
+
+        let mut framebuffer = Framebuffer::new(width, height);
+        render(&mut framebuffer, &data);
+
+        let center = framebuffer.buffer[height / 2 * width + width / 2];
+        assert_eq!(center, u32::from(&Color::new(204, 204, 204)));
+    }
+
+    #[test]
+    fn test_scene_entities_are_rendered() {
+        let (width, height) = (60, 60);
+        let translation = Vec3::new(0.5, -0.25, -1.0);
+        let flat = Model {
+            render_entities: vec![Entity {
+                model_matrix: create_model_matrix(translation, 0.5, Vec3::zeros()),
+                ..create_ocean_planet()
+            }],
+            ..ocean_planet_model(width, height)
+        };
+        let graph = Model {
+            render_entities: vec![],
+            scene: SceneNode::new(Transform {
+                translation,
+                ..Transform::default()
+            })
+            .with_child(SceneNode::with_entity(
+                Transform {
+                    scale: 0.5,
+                    ..Transform::default()
+                },
+                create_ocean_planet(),
+            )),
+            ..ocean_planet_model(width, height)
+        };
+
+        let mut flat_framebuffer = Framebuffer::new(width, height);
+        render(&mut flat_framebuffer, &flat);
+        let mut graph_framebuffer = Framebuffer::new(width, height);
+        render(&mut graph_framebuffer, &graph);
+
+        assert!(graph_framebuffer.buffer.iter().any(|&c| c != 0));
+        assert_eq!(flat_framebuffer.buffer, graph_framebuffer.buffer);
+    }
+
+    #[test]
+    fn test_indexed_vertices_are_shaded_once() {
+        let data = ocean_planet_model(120, 90);
+        let model_matrix = create_model_matrix(Vec3::zeros(), 0.5, Vec3::new(0.3, 0.6, 0.0));
+        let normal_matrix = create_normal_matrix(&model_matrix);
+        let shade = |vertices: &[Vertex]| {
+            apply_shaders(
+                vertices,
+                &vertex_shader,
+                &data.uniforms,
+                &model_matrix,
+                &normal_matrix,
+            )
+        };
+
+        for path in ["BlueFalcon.obj", "sphere.obj"] {
+            let mesh = &mesh_cache().load(path).unwrap()[0];
+            let flat: Vec<Vertex> = mesh.triangles(&mesh.vertices).flatten().cloned().collect();
+
+            let flat_vertices = shade(&flat);
+            let indexed_vertices = shade(&mesh.vertices);
+            let indexed_triangles = assembly(mesh, &indexed_vertices);
+
+            // Shared vertices go through the vertex shader once instead of once per triangle.
+            assert!(mesh.vertices.len() < flat.len());
+            assert_eq!(indexed_triangles.len() * 3, flat_vertices.len());
+            for (indexed, flat) in indexed_triangles.iter().flatten().zip(&flat_vertices) {
+                assert_eq!(indexed.clip_position, flat.clip_position);
+                assert_eq!(indexed.normal, flat.normal);
+            }
+        }
+    }
+
+    /// The ocean planet drawn on `sphere.obj`, the mesh the parallel renderer was measured with.
+    fn sphere_model(width: usize, height: usize) -> Model {
+        let mut data = ocean_planet_model(width, height);
+        data.render_entities[0].objs = mesh_cache().load("sphere.obj").unwrap();
+
+        data
+    }
+
+    /// Renders `data` once with a single thread and once with every thread of rayon.
+    fn render_serial_and_parallel(
+        data: &Model,
+        (width, height): (usize, usize),
+    ) -> ((Framebuffer, Duration), (Framebuffer, Duration)) {
+        let single_thread = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap();
+        let mut serial = Framebuffer::new(width, height);
+        let start = Instant::now();
+        single_thread.install(|| render(&mut serial, data));
+        let serial_time = start.elapsed();
+
+        let mut parallel = Framebuffer::new(width, height);
+        let start = Instant::now();
+        render(&mut parallel, data);
+        let parallel_time = start.elapsed();
+
+        ((serial, serial_time), (parallel, parallel_time))
+    }
+
+    #[test]
+    fn test_parallel_frame() {
+        let dimensions = (200, 200);
+        let data = sphere_model(dimensions.0, dimensions.1);
+
+        let ((serial, _), (parallel, _)) = render_serial_and_parallel(&data, dimensions);
+
+        assert!(parallel.buffer.iter().any(|&c| c != 0));
+        assert_eq!(serial.buffer, parallel.buffer);
+    }
+
+    /// Measures the speedup of shading in parallel.
+    /// Run it with `cargo test --release -- --ignored --nocapture`.
+    #[test]
+    #[ignore = "timing benchmark"]
+    fn bench_parallel_frame() {
+        let dimensions = (800, 800);
+        let data = sphere_model(dimensions.0, dimensions.1);
+        // The first frame warms up the mesh cache and the thread pool.
+        render_serial_and_parallel(&data, dimensions);
+
+        let ((_, serial_time), (_, parallel_time)) = render_serial_and_parallel(&data, dimensions);
+
+        println!(
+            "Rendered sphere.obj: 1 thread {serial_time:?}, {} threads {parallel_time:?} ({:.2}x)",
+            rayon::current_num_threads(),
+            serial_time.as_secs_f32() / parallel_time.as_secs_f32()
+        );
     }
 }
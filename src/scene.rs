@@ -0,0 +1,210 @@
+use nalgebra_glm::{Mat4, Vec3};
+
+use crate::{shader::create_model_matrix, Entity};
+
+/// The translation, rotation and scale of a node relative to its parent.
+#[derive(Debug, Clone)]
+pub struct Transform {
+    pub translation: Vec3,
+    /// Rotation around each axis, in radians.
+    pub rotation: Vec3,
+    pub scale: f32,
+}
+
+impl Transform {
+    /// Creates the matrix that takes points from the space of the node into its parent's.
+    pub fn matrix(&self) -> Mat4 {
+        create_model_matrix(self.translation, self.scale, self.rotation)
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform {
+            translation: Vec3::zeros(),
+            rotation: Vec3::zeros(),
+            scale: 1.0,
+        }
+    }
+}
+
+/// A node of the scene graph.
+///
+/// Children are placed relative to their parent, so moving, rotating or scaling a node carries
+/// every node below it along. The `model_matrix` of the entity of a node only affects the entity
+/// itself, which allows scaling a planet without pushing away the moons orbiting it.
+#[derive(Default)]
+pub struct SceneNode {
+    pub transform: Transform,
+    pub entity: Option<Entity>,
+    pub children: Vec<SceneNode>,
+}
+
+impl SceneNode {
+    /// Creates an empty node, useful to group other nodes.
+    pub fn new(transform: Transform) -> Self {
+        SceneNode {
+            transform,
+            entity: None,
+            children: vec![],
+        }
+    }
+
+    /// Creates a node that draws the given `entity`.
+    pub fn with_entity(transform: Transform, entity: Entity) -> Self {
+        SceneNode {
+            entity: Some(entity),
+            ..SceneNode::new(transform)
+        }
+    }
+
+    /// Adds `child` below this node.
+    pub fn with_child(mut self, child: SceneNode) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    /// Visits this node and every node below it, depth first, along with its world matrix.
+    ///
+    /// * `parent_matrix`: The world matrix of the parent of this node.
+    /// * `visit`: Called with every node and the matrix that takes it into world space.
+    pub fn traverse<'a, F>(&'a self, parent_matrix: &Mat4, visit: &mut F)
+    where
+        F: FnMut(&'a SceneNode, &Mat4),
+    {
+        let world_matrix = parent_matrix * self.transform.matrix();
+
+        visit(self, &world_matrix);
+        for child in &self.children {
+            child.traverse(&world_matrix, visit);
+        }
+    }
+
+    /// Collects every entity of the graph, along the model matrix it must be rendered with.
+    pub fn entities(&self) -> Vec<(&Entity, Mat4)> {
+        let mut entities = vec![];
+
+        self.traverse(&Mat4::identity(), &mut |node, world_matrix| {
+            if let Some(entity) = &node.entity {
+                entities.push((entity, world_matrix * entity.model_matrix));
+            }
+        });
+
+        entities
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+    use crate::{assets::ObjHandle, shader::vertex_shader};
+    use nalgebra_glm::{vec3, vec4};
+
+    fn entity(model_matrix: Mat4) -> Entity {
+        Entity {
+            objs: ObjHandle::default(),
+            vertex_shader: Box::new(vertex_shader),
+            shaders: vec![],
+            model_matrix,
+            orbit: None,
+        }
+    }
+
+    fn transform(translation: Vec3, rotation: Vec3, scale: f32) -> Transform {
+        Transform {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    fn origin_of(matrix: &Mat4) -> Vec3 {
+        (matrix * vec4(0.0, 0.0, 0.0, 1.0)).xyz()
+    }
+
+    fn assert_vec_eq(actual: Vec3, expected: Vec3) {
+        assert!(
+            (actual - expected).abs().max() < 1e-4,
+            "{actual:?} != {expected:?}"
+        );
+    }
+
+    /// A sun turned a quarter around Y, a planet 10 units away from it and a moon 2 units away
+    /// from the planet, which is scaled by 2.
+    fn solar_system() -> SceneNode {
+        let moon = SceneNode::with_entity(
+            transform(vec3(2.0, 0.0, 0.0), Vec3::zeros(), 0.5),
+            entity(Mat4::identity()),
+        );
+        let planet = SceneNode::with_entity(
+            transform(vec3(10.0, 0.0, 0.0), Vec3::zeros(), 2.0),
+            entity(Mat4::identity()),
+        )
+        .with_child(moon);
+
+        SceneNode::with_entity(
+            transform(vec3(1.0, 0.0, 0.0), vec3(0.0, PI / 2.0, 0.0), 1.0),
+            entity(create_model_matrix(Vec3::zeros(), 3.0, Vec3::zeros())),
+        )
+        .with_child(planet)
+    }
+
+    #[test]
+    fn test_nested_world_matrices() {
+        let mut world_matrices = vec![];
+        solar_system().traverse(&Mat4::identity(), &mut |_, world_matrix| {
+            world_matrices.push(*world_matrix)
+        });
+
+        let [sun, planet, moon] = world_matrices[..] else {
+            panic!("Expected 3 nodes, got {}", world_matrices.len());
+        };
+        // A quarter turn around Y takes +X into -Z.
+        assert_vec_eq(origin_of(&sun), vec3(1.0, 0.0, 0.0));
+        assert_vec_eq(origin_of(&planet), vec3(1.0, 0.0, -10.0));
+        // The moon is twice as far away because the planet is scaled.
+        assert_vec_eq(origin_of(&moon), vec3(1.0, 0.0, -14.0));
+        // Scales multiply along the way, and the rotation is inherited.
+        let moon_x = (moon * vec4(1.0, 0.0, 0.0, 0.0)).xyz();
+        assert_vec_eq(moon_x, vec3(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn test_entity_model_matrix_is_not_inherited() {
+        let scene = solar_system();
+
+        let entities = scene.entities();
+
+        assert_eq!(entities.len(), 3);
+        let (_, sun) = entities[0];
+        let (_, planet) = entities[1];
+        // The sun is drawn 3 times bigger, but its children are placed as if it wasn't.
+        assert_vec_eq((sun * vec4(1.0, 0.0, 0.0, 0.0)).xyz(), vec3(0.0, 0.0, -3.0));
+        assert_vec_eq(origin_of(&planet), vec3(1.0, 0.0, -10.0));
+    }
+
+    #[test]
+    fn test_moving_a_parent_moves_its_children() {
+        let mut scene = solar_system();
+        scene.transform.translation = vec3(0.0, 5.0, 0.0);
+
+        let entities = scene.entities();
+
+        let (_, moon) = entities[2];
+        assert_vec_eq(origin_of(&moon), vec3(0.0, 5.0, -14.0));
+    }
+
+    #[test]
+    fn test_empty_nodes_group_children() {
+        let scene = SceneNode::new(transform(vec3(0.0, 0.0, -3.0), Vec3::zeros(), 1.0)).with_child(
+            SceneNode::with_entity(Transform::default(), entity(Mat4::identity())),
+        );
+
+        let entities = scene.entities();
+
+        assert_eq!(entities.len(), 1);
+        assert_vec_eq(origin_of(&entities[0].1), vec3(0.0, 0.0, -3.0));
+    }
+}
@@ -0,0 +1,1126 @@
+//! Scenes described in TOML files, so planets can be tweaked without recompiling.
+//!
+//! ```toml
+//! ambient_intensity = 0.05
+//!
+//! [camera]
+//! eye = [0.0, 0.0, 10.0]
+//!
+//! [[lights]]
+//! type = "directional"
+//! direction = [0.0, -0.5, -1.0]
+//!
+//! [[entities]]
+//! mesh = "sphere.obj"
+//! scale = 2.0
+//!
+//! [[entities.shaders]]
+//! type = "base_color"
+//! blend = "replace"
+//! color = 0xc2e9ed
+//! ```
+//!
+//! Colors are written either as a hex number or as an `[r, g, b]` array. Mesh paths are relative
+//! to the directory of the scene file, and are loaded through `assets::mesh_cache`, so they can be
+//! OBJ, glTF, STL or PLY files. Shaders take the same parameters as the structs of
+//! `fragment_shaders`, their `type` is the name of the shader in snake case without the `Shader`
+//! suffix. The `texture` shader takes the `path` of a BMP image, relative like mesh paths.
+
+use std::{
+    fmt::Display,
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use fastnoise_lite::{CellularDistanceFunction, CellularReturnType, FractalType};
+use nalgebra_glm::Vec3;
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::{
+    assets::mesh_cache,
+    blenders::BlendMode,
+    camera::Camera,
+    color::Color,
+    fragment::Space,
+    fragment_shaders::{
+        AliveCheckerboard, BaseColor, BlinnPhong, CellularConfig, CellularShader, FBmShader,
+        FractalConfig, GlowShader, Intensity, Interference, MaterialDiffuse, MovingStripes,
+        PatternCoordinates, Stripe, TextureShader,
+    },
+    light::{AmbientLightIntensity, Light},
+    material::Material,
+    scene::SceneNode,
+    shader::{
+        create_model_matrix, create_projection_matrix, create_view_matrix, create_viewport_matrix,
+        vertex_shader, FragmentShader, Uniforms,
+    },
+    solar_system::{update_orbits, Orbit},
+    texture::{Filter, Texture, WrapMode},
+    Entity, EntityShader, Model,
+};
+
+#[derive(Debug)]
+pub enum SceneError {
+    /// The scene file couldn't be read.
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    /// The scene file isn't valid TOML or doesn't describe a scene.
+    Parse {
+        line: Option<usize>,
+        error: toml::de::Error,
+    },
+    /// A value is well formed but can't be used, like a mesh that can't be loaded.
+    Invalid { line: usize, message: String },
+}
+
+impl Display for SceneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SceneError::Io { path, error } => {
+                write!(f, "Couldn't read scene `{}`: {error}", path.display())
+            }
+            SceneError::Parse { error, .. } => write!(f, "{error}"),
+            SceneError::Invalid { line, message } => write!(f, "Error at line {line}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl SceneError {
+    /// Describes the error in a single line, short enough for a window title.
+    pub fn summary(&self) -> String {
+        match self {
+            SceneError::Parse {
+                line: Some(line),
+                error,
+            } => format!("Error at line {line}: {}", single_line(error.message())),
+            SceneError::Parse { line: None, error } => single_line(error.message()),
+            _ => self.to_string(),
+        }
+    }
+}
+
+fn single_line(message: &str) -> String {
+    message.lines().collect::<Vec<_>>().join(", ")
+}
+
+/// Loads the scene described by the file at `path`.
+///
+/// * `framebuffer_dimensions`: The width and height of the framebuffer the scene is rendered to.
+pub fn load_scene(
+    path: impl AsRef<Path>,
+    framebuffer_dimensions: (usize, usize),
+) -> Result<Model, SceneError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(|error| SceneError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+
+    parse_scene(&source, base_dir, framebuffer_dimensions)
+}
+
+/// Parses the scene described by `source`.
+///
+/// * `base_dir`: The directory mesh paths are relative to.
+/// * `framebuffer_dimensions`: The width and height of the framebuffer the scene is rendered to.
+pub fn parse_scene(
+    source: &str,
+    base_dir: &Path,
+    framebuffer_dimensions: (usize, usize),
+) -> Result<Model, SceneError> {
+    let SceneDescription {
+        ambient_intensity,
+        camera,
+        lights,
+        entities,
+    } = toml::from_str(source).map_err(|error| SceneError::Parse {
+        line: error.span().map(|span| line_of(source, &span)),
+        error,
+    })?;
+    let (width, height) = framebuffer_dimensions;
+
+    let camera = Camera::new(camera.eye.into(), camera.center.into(), camera.up.into());
+    let entities = entities
+        .into_iter()
+        .map(|entity| entity.build(source, base_dir))
+        .collect::<Result<_, _>>()?;
+
+    Ok(Model {
+        entities,
+        render_entities: vec![],
+        scene: SceneNode::default(),
+        uniforms: Uniforms {
+            view_matrix: create_view_matrix(camera.eye, camera.center, camera.up),
+            projection_matrix: create_projection_matrix(width as f32, height as f32),
+            viewport_matrix: create_viewport_matrix(width as f32, height as f32),
+            time: 0.0,
+            camera_position: camera.eye,
+            lights: lights.into_iter().map(Light::from).collect(),
+            ambient_intensity,
+        },
+        rotation: Vec3::zeros(),
+        translation: Vec3::zeros(),
+        scale: 1.0,
+        camera,
+    })
+}
+
+/// Replaces what `scene` describes in the `current` model, keeping the state of the camera and
+/// the time.
+pub fn reload_scene(current: Model, scene: Model) -> Model {
+    let Model {
+        mut entities,
+        render_entities,
+        scene,
+        uniforms:
+            Uniforms {
+                lights,
+                ambient_intensity,
+                ..
+            },
+        ..
+    } = scene;
+    let Model { uniforms, .. } = current;
+
+    update_orbits(&mut entities, uniforms.time);
+    let uniforms = Uniforms {
+        lights,
+        ambient_intensity,
+        ..uniforms
+    };
+
+    Model {
+        entities,
+        render_entities,
+        scene,
+        uniforms,
+        ..current
+    }
+}
+
+/// Keeps track of the modification time of a scene file, to reload it when it changes.
+pub struct SceneWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl SceneWatcher {
+    /// Starts watching the file at `path`, as it is right now.
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let modified = modified_time(&path);
+
+        SceneWatcher { path, modified }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Checks if the file was modified since it was last checked.
+    /// A missing file isn't considered a change, so editors can replace it while saving.
+    pub fn has_changed(&mut self) -> bool {
+        let modified = modified_time(&self.path);
+        if modified.is_none() || modified == self.modified {
+            return false;
+        }
+
+        self.modified = modified;
+        true
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Finds the line (starting at 1) of `source` where `span` starts.
+fn line_of(source: &str, span: &Range<usize>) -> usize {
+    source[..span.start].matches('\n').count() + 1
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDescription {
+    #[serde(default)]
+    ambient_intensity: AmbientLightIntensity,
+    camera: CameraDescription,
+    #[serde(default)]
+    lights: Vec<LightDescription>,
+    #[serde(default)]
+    entities: Vec<EntityDescription>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDescription {
+    eye: [f32; 3],
+    #[serde(default)]
+    center: [f32; 3],
+    #[serde(default = "default_up")]
+    up: [f32; 3],
+}
+
+fn default_up() -> [f32; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+fn default_one() -> f32 {
+    1.0
+}
+
+fn default_white() -> ColorDescription {
+    ColorDescription::Rgb([u8::MAX; 3])
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum LightDescription {
+    Point {
+        position: [f32; 3],
+        #[serde(default = "default_white")]
+        color: ColorDescription,
+        #[serde(default = "default_one")]
+        intensity: f32,
+    },
+    Directional {
+        direction: [f32; 3],
+        #[serde(default = "default_white")]
+        color: ColorDescription,
+        #[serde(default = "default_one")]
+        intensity: f32,
+    },
+}
+
+impl From<LightDescription> for Light {
+    fn from(light: LightDescription) -> Self {
+        match light {
+            LightDescription::Point {
+                position,
+                color,
+                intensity,
+            } => Light::point(position.into(), color.into(), intensity),
+            LightDescription::Directional {
+                direction,
+                color,
+                intensity,
+            } => Light::directional(direction.into(), color.into(), intensity),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ColorDescription {
+    Hex(u32),
+    Rgb([u8; 3]),
+}
+
+impl From<ColorDescription> for Color {
+    fn from(color: ColorDescription) -> Self {
+        match color {
+            ColorDescription::Hex(hex) => hex.into(),
+            ColorDescription::Rgb([r, g, b]) => Color::new(r, g, b),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EntityDescription {
+    mesh: Spanned<String>,
+    #[serde(default)]
+    translation: [f32; 3],
+    #[serde(default)]
+    rotation: [f32; 3],
+    #[serde(default = "default_one")]
+    scale: f32,
+    orbit: Option<OrbitDescription>,
+    #[serde(default)]
+    shaders: Vec<ShaderDescription>,
+}
+
+impl EntityDescription {
+    fn build(self, source: &str, base_dir: &Path) -> Result<Entity, SceneError> {
+        let EntityDescription {
+            mesh,
+            translation,
+            rotation,
+            scale,
+            orbit,
+            shaders,
+        } = self;
+
+        let objs = mesh_cache()
+            .load(base_dir.join(mesh.get_ref()))
+            .map_err(|error| SceneError::Invalid {
+                line: line_of(source, &mesh.span()),
+                message: format!("Couldn't load mesh `{}`: {error}", mesh.get_ref()),
+            })?;
+        let shaders = shaders
+            .into_iter()
+            .map(|shader| shader.build(source, base_dir))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Entity {
+            objs,
+            vertex_shader: Box::new(vertex_shader),
+            shaders,
+            model_matrix: create_model_matrix(translation.into(), scale, rotation.into()),
+            orbit: orbit.map(|orbit| orbit.into_orbit(scale)),
+        })
+    }
+}
+
+/// An `Orbit`, the scale of the body is the one of the entity.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OrbitDescription {
+    radius: f32,
+    #[serde(default)]
+    period: f32,
+    #[serde(default)]
+    inclination: f32,
+    #[serde(default)]
+    spin_period: f32,
+    #[serde(default)]
+    phase: f32,
+}
+
+impl OrbitDescription {
+    fn into_orbit(self, scale: f32) -> Orbit {
+        let OrbitDescription {
+            radius,
+            period,
+            inclination,
+            spin_period,
+            phase,
+        } = self;
+
+        Orbit {
+            radius,
+            period,
+            inclination,
+            spin_period,
+            phase,
+            scale,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(try_from = "ShaderTable")]
+struct ShaderDescription {
+    blend: Spanned<BlendModeDescription>,
+    shader: ShaderKind,
+}
+
+/// The table of a shader, before the parameters are read as the `ShaderKind` of its `type`.
+///
+/// `serde` can't reject unknown fields next to a flattened field, so the parameters are read on
+/// their own instead. That way a misspelled parameter is an error instead of its default.
+#[derive(Deserialize)]
+struct ShaderTable {
+    blend: Spanned<BlendModeDescription>,
+    #[serde(flatten)]
+    parameters: toml::Table,
+}
+
+impl TryFrom<ShaderTable> for ShaderDescription {
+    type Error = toml::de::Error;
+
+    fn try_from(ShaderTable { blend, parameters }: ShaderTable) -> Result<Self, Self::Error> {
+        Ok(ShaderDescription {
+            blend,
+            shader: ShaderKind::deserialize(toml::Value::Table(parameters))?,
+        })
+    }
+}
+
+impl ShaderDescription {
+    fn build(self, source: &str, base_dir: &Path) -> Result<EntityShader, SceneError> {
+        let ShaderDescription { blend, shader } = self;
+        let line = line_of(source, &blend.span());
+        let invalid = |message: &str| SceneError::Invalid {
+            line,
+            message: message.to_string(),
+        };
+
+        let shader: Box<dyn FragmentShader> = match shader {
+            ShaderKind::Stripe {
+                stripe_width,
+                colors,
+            } => {
+                if colors.is_empty() {
+                    return Err(invalid("A stripe shader needs at least one color"));
+                }
+                Box::new(Stripe {
+                    stripe_width,
+                    colors: colors.into_iter().map(Color::from).collect(),
+                })
+            }
+            ShaderKind::MovingStripes {
+                speed,
+                stripe_width,
+                colors: [color1, color2],
+            } => Box::new(MovingStripes {
+                speed,
+                stripe_width,
+                colors: (color1.into(), color2.into()),
+            }),
+            ShaderKind::Glow {
+                stripe_width,
+                glow_size,
+                red,
+                blue,
+            } => Box::new(GlowShader {
+                stripe_width,
+                glow_size,
+                red,
+                blue,
+            }),
+            ShaderKind::Intensity {} => Box::new(Intensity),
+            ShaderKind::BaseColor { color } => Box::new(BaseColor {
+                color: color.into(),
+            }),
+            ShaderKind::MaterialDiffuse {} => Box::new(MaterialDiffuse),
+            ShaderKind::Texture { path, filter, wrap } => {
+                let texture = Texture::load(base_dir.join(&path)).map_err(|error| {
+                    invalid(&format!("Couldn't load texture `{path}`: {error}"))
+                })?;
+                Box::new(TextureShader {
+                    texture: Arc::new(texture),
+                    filter: filter.into(),
+                    wrap: wrap.into(),
+                })
+            }
+            ShaderKind::Fbm {
+                zoom,
+                speed,
+                fractal,
+                color,
+            } => Box::new(FBmShader::new(zoom, speed, fractal, color.into())),
+            ShaderKind::Cellular {
+                zoom,
+                speed,
+                fractal,
+                cellular,
+                color,
+            } => Box::new(CellularShader::new(
+                zoom,
+                speed,
+                fractal,
+                cellular,
+                color.into(),
+            )),
+            ShaderKind::AliveCheckerboard {
+                cell_size,
+                speed,
+                pulse_speed,
+                colors,
+                coordinates,
+            } => {
+                if colors.is_empty() {
+                    return Err(invalid("A checkerboard shader needs at least one color"));
+                }
+                Box::new(AliveCheckerboard {
+                    cell_size,
+                    speed,
+                    pulse_speed,
+                    colors: colors.into_iter().map(Color::from).collect(),
+                    coordinates: coordinates.into(),
+                })
+            }
+            ShaderKind::Interference {
+                frequency,
+                speed,
+                colors: [color1, color2, color3],
+                coordinates,
+            } => Box::new(Interference {
+                frequency,
+                speed,
+                colors: (color1.into(), color2.into(), color3.into()),
+                coordinates: coordinates.into(),
+            }),
+            ShaderKind::BlinnPhong { material } => Box::new(BlinnPhong {
+                material: material.into(),
+            }),
+        };
+
+        Ok((shader, blend.into_inner().into()))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ShaderKind {
+    Stripe {
+        stripe_width: f32,
+        colors: Vec<ColorDescription>,
+    },
+    MovingStripes {
+        speed: f32,
+        stripe_width: f32,
+        colors: [ColorDescription; 2],
+    },
+    Glow {
+        stripe_width: f32,
+        glow_size: f32,
+        red: f32,
+        blue: f32,
+    },
+    // Empty struct variants, since unit variants of tagged enums ignore unknown fields.
+    Intensity {},
+    BaseColor {
+        color: ColorDescription,
+    },
+    MaterialDiffuse {},
+    Texture {
+        path: String,
+        #[serde(default)]
+        filter: FilterDescription,
+        #[serde(default)]
+        wrap: WrapModeDescription,
+    },
+    Fbm {
+        zoom: f32,
+        speed: f32,
+        #[serde(with = "FractalConfigDescription")]
+        fractal: FractalConfig,
+        color: ColorDescription,
+    },
+    Cellular {
+        zoom: f32,
+        speed: f32,
+        #[serde(with = "FractalConfigDescription")]
+        fractal: FractalConfig,
+        #[serde(with = "CellularConfigDescription")]
+        cellular: CellularConfig,
+        color: ColorDescription,
+    },
+    AliveCheckerboard {
+        cell_size: f32,
+        speed: f32,
+        pulse_speed: f32,
+        colors: Vec<ColorDescription>,
+        coordinates: CoordinatesDescription,
+    },
+    Interference {
+        frequency: f32,
+        speed: f32,
+        colors: [ColorDescription; 3],
+        coordinates: CoordinatesDescription,
+    },
+    BlinnPhong {
+        material: MaterialDescription,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum BlendModeDescription {
+    Normal,
+    Add,
+    Subtract,
+    Multiply,
+    Screen,
+    Overlay,
+    Darken,
+    Lighten,
+    Dodge,
+    Burn,
+    HardLight,
+    SoftLight,
+    Difference,
+    Exclusion,
+    Replace,
+}
+
+impl From<BlendModeDescription> for BlendMode {
+    fn from(blend: BlendModeDescription) -> Self {
+        match blend {
+            BlendModeDescription::Normal => BlendMode::Normal,
+            BlendModeDescription::Add => BlendMode::Add,
+            BlendModeDescription::Subtract => BlendMode::Subtract,
+            BlendModeDescription::Multiply => BlendMode::Multiply,
+            BlendModeDescription::Screen => BlendMode::Screen,
+            BlendModeDescription::Overlay => BlendMode::Overlay,
+            BlendModeDescription::Darken => BlendMode::Darken,
+            BlendModeDescription::Lighten => BlendMode::Lighten,
+            BlendModeDescription::Dodge => BlendMode::Dodge,
+            BlendModeDescription::Burn => BlendMode::Burn,
+            BlendModeDescription::HardLight => BlendMode::HardLight,
+            BlendModeDescription::SoftLight => BlendMode::SoftLight,
+            BlendModeDescription::Difference => BlendMode::Difference,
+            BlendModeDescription::Exclusion => BlendMode::Exclusion,
+            BlendModeDescription::Replace => BlendMode::Replace,
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+enum FilterDescription {
+    Nearest,
+    Bilinear,
+    #[default]
+    Trilinear,
+    Anisotropic,
+}
+
+impl From<FilterDescription> for Filter {
+    fn from(filter: FilterDescription) -> Self {
+        match filter {
+            FilterDescription::Nearest => Filter::Nearest,
+            FilterDescription::Bilinear => Filter::Bilinear,
+            FilterDescription::Trilinear => Filter::Trilinear,
+            FilterDescription::Anisotropic => Filter::Anisotropic,
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+enum WrapModeDescription {
+    #[default]
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+impl From<WrapModeDescription> for WrapMode {
+    fn from(wrap: WrapModeDescription) -> Self {
+        match wrap {
+            WrapModeDescription::Repeat => WrapMode::Repeat,
+            WrapModeDescription::Clamp => WrapMode::Clamp,
+            WrapModeDescription::Mirror => WrapMode::Mirror,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum CoordinatesDescription {
+    TexCoords,
+    Object,
+    World,
+}
+
+impl From<CoordinatesDescription> for PatternCoordinates {
+    fn from(coordinates: CoordinatesDescription) -> Self {
+        match coordinates {
+            CoordinatesDescription::TexCoords => PatternCoordinates::TexCoords,
+            CoordinatesDescription::Object => PatternCoordinates::Position(Space::Object),
+            CoordinatesDescription::World => PatternCoordinates::Position(Space::World),
+        }
+    }
+}
+
+/// A `Material`, every value not written takes the one of `Material::default`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MaterialDescription {
+    diffuse: Option<ColorDescription>,
+    specular: Option<f32>,
+    albedo: Option<(f32, f32)>,
+    reflectivity: Option<f32>,
+    transparency: Option<f32>,
+    refractive_index: Option<f32>,
+}
+
+impl From<MaterialDescription> for Material {
+    fn from(material: MaterialDescription) -> Self {
+        let default = Material::default();
+
+        Material {
+            diffuse: material.diffuse.map_or(default.diffuse, Color::from),
+            specular: material.specular.unwrap_or(default.specular),
+            albedo: material.albedo.unwrap_or(default.albedo),
+            reflectivity: material.reflectivity.unwrap_or(default.reflectivity),
+            transparency: material.transparency.unwrap_or(default.transparency),
+            refractive_index: material
+                .refractive_index
+                .unwrap_or(default.refractive_index),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(remote = "FractalConfig", deny_unknown_fields)]
+struct FractalConfigDescription {
+    octaves: i32,
+    lacunarity: f32,
+    gain: f32,
+    #[serde(default)]
+    weighted_strength: f32,
+    #[serde(with = "FractalTypeDescription")]
+    f_type: FractalType,
+}
+
+#[derive(Deserialize)]
+#[serde(remote = "CellularConfig", deny_unknown_fields)]
+struct CellularConfigDescription {
+    #[serde(with = "CellularDistanceFunctionDescription")]
+    distance_func: CellularDistanceFunction,
+    #[serde(with = "CellularReturnTypeDescription")]
+    return_type: CellularReturnType,
+    jitter: f32,
+}
+
+#[derive(Deserialize)]
+#[serde(remote = "FractalType", rename_all = "snake_case")]
+enum FractalTypeDescription {
+    None,
+    #[serde(rename = "fbm")]
+    FBm,
+    Ridged,
+    PingPong,
+    DomainWarpProgressive,
+    DomainWarpIndependent,
+}
+
+#[derive(Deserialize)]
+#[serde(remote = "CellularDistanceFunction", rename_all = "snake_case")]
+enum CellularDistanceFunctionDescription {
+    Euclidean,
+    EuclideanSq,
+    Manhattan,
+    Hybrid,
+}
+
+#[derive(Deserialize)]
+#[serde(remote = "CellularReturnType", rename_all = "snake_case")]
+enum CellularReturnTypeDescription {
+    CellValue,
+    Distance,
+    Distance2,
+    Distance2Add,
+    Distance2Sub,
+    Distance2Mul,
+    Distance2Div,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIMENSIONS: (usize, usize) = (100, 100);
+
+    fn parse(source: &str) -> Result<Model, SceneError> {
+        parse_scene(source, Path::new(""), DIMENSIONS)
+    }
+
+    #[test]
+    fn test_example_scene() {
+        let model = load_scene("scenes/solar_system.toml", DIMENSIONS).unwrap();
+
+        assert_eq!(model.camera.eye, Vec3::new(0.0, 25.0, 55.0));
+        assert_eq!(model.uniforms.lights.len(), 1);
+        assert_eq!(model.entities.len(), 7);
+        assert!(model.entities.iter().all(|entity| !entity.objs.is_empty()));
+        assert!(model.entities[1..].iter().all(|e| e.orbit.is_some()));
+    }
+
+    #[test]
+    fn test_shader_stack() {
+        let model = parse(
+            r#"
+            [camera]
+            eye = [0.0, 0.0, 3.0]
+
+            [[entities]]
+            mesh = "sphere.obj"
+            translation = [1.0, 2.0, 3.0]
+            scale = 0.5
+
+            [[entities.shaders]]
+            type = "base_color"
+            blend = "replace"
+            color = 0x102030
+
+            [[entities.shaders]]
+            type = "stripe"
+            blend = "multiply"
+            stripe_width = 0.1
+            colors = [[255, 0, 0], 0x00ff00]
+
+            [[entities.shaders]]
+            type = "fbm"
+            blend = "screen"
+            zoom = 600.0
+            speed = 4e-2
+            color = 0xe6e6e6
+            fractal = { octaves = 4, lacunarity = 2.0, gain = 0.8, f_type = "fbm" }
+            "#,
+        )
+        .unwrap();
+
+        let entity = &model.entities[0];
+        assert_eq!(entity.shaders.len(), 3);
+        assert!(matches!(entity.shaders[1].1, BlendMode::Multiply));
+        assert_eq!(
+            entity.model_matrix,
+            create_model_matrix(Vec3::new(1.0, 2.0, 3.0), 0.5, Vec3::zeros())
+        );
+        assert!(entity.orbit.is_none());
+        assert_eq!(model.uniforms.camera_position, Vec3::new(0.0, 0.0, 3.0));
+    }
+
+    #[test]
+    fn test_unknown_shader_points_at_its_line() {
+        let error = parse(
+            r#"
+            [camera]
+            eye = [0.0, 0.0, 3.0]
+
+            [[entities]]
+            mesh = "sphere.obj"
+
+            [[entities.shaders]]
+            type = "sparkles"
+            blend = "replace"
+            "#,
+        )
+        .err()
+        .unwrap();
+
+        let message = error.to_string();
+        assert!(message.contains("line 8"), "{message}");
+        assert!(message.contains("sparkles"), "{message}");
+    }
+
+    #[test]
+    fn test_invalid_value_points_at_its_line() {
+        let error = parse(
+            r#"
+            [camera]
+            eye = [0.0, 0.0, 3.0]
+
+            [[entities]]
+            mesh = "sphere.obj"
+
+            [[entities.shaders]]
+            type = "cellular"
+            blend = "add"
+            zoom = 2000.0
+            speed = 0.2
+            color = 0xc2e9ed
+            fractal = { octaves = 4, lacunarity = 0.5, gain = 1.0, f_type = "ping_pong" }
+            cellular = { distance_func = "taxicab", return_type = "distance", jitter = 1.0 }
+            "#,
+        )
+        .err()
+        .unwrap();
+
+        // Errors inside a shader point at the start of its table.
+        let message = error.to_string();
+        assert!(message.contains("line 8"), "{message}");
+        assert!(message.contains("taxicab"), "{message}");
+    }
+
+    #[test]
+    fn test_misspelled_parameter_points_at_its_line() {
+        let scene = |parameters: &str| {
+            format!(
+                r#"
+                [camera]
+                eye = [0.0, 0.0, 3.0]
+
+                [[entities]]
+                mesh = "sphere.obj"
+
+                [[entities.shaders]]
+                blend = "replace"
+                {parameters}
+                "#
+            )
+        };
+
+        for parameters in [
+            r#"type = "texture"
+               path = "earth.bmp"
+               filtr = "nearest""#,
+            r#"type = "intensity"
+               strength = 2.0"#,
+        ] {
+            let error = parse(&scene(parameters)).err().unwrap();
+
+            let message = error.to_string();
+            assert!(message.contains("line 8"), "{message}");
+            assert!(
+                message.contains("filtr") || message.contains("strength"),
+                "{message}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_missing_mesh_points_at_its_line() {
+        let error = parse(
+            r#"
+            [camera]
+            eye = [0.0, 0.0, 3.0]
+
+            [[entities]]
+            mesh = "sphere.obj"
+
+            [[entities]]
+            mesh = "does_not_exist.obj"
+            "#,
+        )
+        .err()
+        .unwrap();
+
+        assert!(
+            matches!(error, SceneError::Invalid { line: 9, .. }),
+            "{error}"
+        );
+        assert!(error.to_string().contains("does_not_exist.obj"));
+    }
+
+    #[test]
+    fn test_missing_texture_points_at_its_line() {
+        let error = parse(
+            r#"
+            [camera]
+            eye = [0.0, 0.0, 3.0]
+
+            [[entities]]
+            mesh = "sphere.obj"
+
+            [[entities.shaders]]
+            type = "texture"
+            blend = "replace"
+            path = "does_not_exist.bmp"
+            filter = "nearest"
+            wrap = "clamp"
+            "#,
+        )
+        .err()
+        .unwrap();
+
+        assert!(
+            matches!(error, SceneError::Invalid { line: 10, .. }),
+            "{error}"
+        );
+        assert!(error.to_string().contains("does_not_exist.bmp"));
+    }
+
+    #[test]
+    fn test_empty_colors_are_rejected() {
+        let error = parse(
+            r#"
+            [camera]
+            eye = [0.0, 0.0, 3.0]
+
+            [[entities]]
+            mesh = "sphere.obj"
+
+            [[entities.shaders]]
+            type = "stripe"
+            blend = "replace"
+            stripe_width = 0.1
+            colors = []
+            "#,
+        )
+        .err()
+        .unwrap();
+
+        assert!(
+            matches!(error, SceneError::Invalid { line: 10, .. }),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn test_parse_error_summary_fits_in_a_line() {
+        let error = parse("[camera]\neye = [0.0, 0.0,\n").err().unwrap();
+
+        let summary = error.summary();
+        assert!(matches!(error, SceneError::Parse { line: Some(_), .. }));
+        assert!(summary.starts_with("Error at line"), "{summary}");
+        assert!(!summary.contains('\n'), "{summary}");
+    }
+
+    #[test]
+    fn test_reload_keeps_camera_and_time() {
+        let mut current = parse(
+            r#"
+            [camera]
+            eye = [0.0, 0.0, 3.0]
+
+            [[entities]]
+            mesh = "sphere.obj"
+            "#,
+        )
+        .unwrap();
+        current.camera.zoom(1.0);
+        current.uniforms.camera_position = current.camera.eye;
+        current.uniforms.time = 1500.0;
+        let eye = current.camera.eye;
+
+        let reloaded = reload_scene(
+            current,
+            parse(
+                r#"
+                ambient_intensity = 0.25
+
+                [camera]
+                eye = [0.0, 0.0, 30.0]
+
+                [[lights]]
+                type = "point"
+                position = [0.0, 0.0, 0.0]
+
+                [[entities]]
+                mesh = "sphere.obj"
+                orbit = { radius = 10.0, period = 6000.0 }
+
+                [[entities.shaders]]
+                type = "intensity"
+                blend = "replace"
+                "#,
+            )
+            .unwrap(),
+        );
+
+        assert_eq!(reloaded.camera.eye, eye);
+        assert_eq!(reloaded.uniforms.camera_position, eye);
+        assert_eq!(reloaded.uniforms.time, 1500.0);
+        assert_eq!(reloaded.uniforms.ambient_intensity, 0.25);
+        assert_eq!(reloaded.uniforms.lights.len(), 1);
+        assert_eq!(reloaded.entities[0].shaders.len(), 1);
+        // The orbit continues from the current time.
+        let center = reloaded.entities[0].model_matrix.column(3).xyz();
+        assert!((center - Vec3::new(0.0, 0.0, -10.0)).abs().max() < 1e-4);
+    }
+
+    #[test]
+    fn test_watcher_detects_changes() {
+        let path = crate::test_utils::temp_path("watched_scene.toml");
+        fs::write(&path, "").unwrap();
+        let mut watcher = SceneWatcher::new(&path);
+
+        assert!(!watcher.has_changed());
+
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + std::time::Duration::from_secs(10))
+            .unwrap();
+        assert!(watcher.has_changed());
+        assert!(!watcher.has_changed());
+
+        fs::remove_file(&path).unwrap();
+        assert!(!watcher.has_changed());
+    }
+}
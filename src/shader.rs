@@ -1,54 +1,74 @@
 use std::f32::consts::PI;
 
-use fastnoise_lite::{CellularDistanceFunction, CellularReturnType, FastNoiseLite, FractalType};
-use nalgebra_glm::{vec2, vec3, vec4, Mat4, Vec3};
-
-use crate::{clamp_with_universe, color::Color, fragment::Fragment, vertex::Vertex, EntityShader};
-
-pub enum ShaderType {
-    Stripe {
-        stripe_width: f32,
-    },
-    MovingStripes {
-        speed: f32,
-        stripe_width: f32,
-    },
-    GlowShader {
-        stripe_width: f32,
-        glow_size: f32,
-        red: f32,
-        blue: f32,
-    },
-    AliveCheckerboard,
-    Intensity,
-    BaseColor,
-    FBmShader {
-        zoom: f32,
-        speed: f32,
-        fractal: FractalConfig,
-    },
-    CellularShader {
-        zoom: f32,
-        speed: f32,
-        fractal: FractalConfig,
-        cellular: CellularConfig,
-    },
+use fastnoise_lite::FastNoiseLite;
+use nalgebra_glm::{mat3_to_mat4, mat4_to_mat3, vec4, Mat3, Mat4, Vec3};
+
+use crate::{
+    color::Color,
+    fragment::Fragment,
+    light::{AmbientLightIntensity, Light},
+    vertex::Vertex,
+    EntityShader,
+};
+
+/// A stage of the fragment shader stack of an `Entity`.
+///
+/// Every shader in the stack computes a color for the fragment, which is then blended with the
+/// result of the previous shaders using the `BlendMode` it was paired with.
+///
+/// Fragments are shaded in parallel, so shaders can't mutate themselves while shading.
+/// Any expensive setup (like configuring a noise generator) should be done once when the
+/// shader is created.
+pub trait FragmentShader: Send + Sync {
+    /// Computes the color of the given `fragment`.
+    ///
+    /// * `fragment`: The fragment being shaded.
+    /// * `current_color`: The color computed by the previous shaders in the stack.
+    /// * `uniforms`: The values shared by every fragment in the frame.
+    fn shade(&self, fragment: &Fragment, current_color: &Color, uniforms: &Uniforms) -> Color;
 }
 
-#[derive(Debug, Clone, Copy)]
-pub struct FractalConfig {
-    pub octaves: i32,
-    pub lacunarity: f32,
-    pub gain: f32,
-    pub weighted_strength: f32,
-    pub f_type: FractalType,
+impl<F> FragmentShader for F
+where
+    F: Fn(&Fragment, &Color, &Uniforms) -> Color + Send + Sync,
+{
+    fn shade(&self, fragment: &Fragment, current_color: &Color, uniforms: &Uniforms) -> Color {
+        self(fragment, current_color, uniforms)
+    }
 }
 
-#[derive(Debug, Clone, Copy)]
-pub struct CellularConfig {
-    pub distance_func: CellularDistanceFunction,
-    pub return_type: CellularReturnType,
-    pub jitter: f32,
+/// Transforms the vertices of an `Entity` from object space into screen space.
+pub trait VertexShader: Send + Sync {
+    /// Transforms the given `vertex`.
+    ///
+    /// The returned vertex must have its `clip_position` set and be projected into the screen
+    /// (see `Vertex::project`), so it can be clipped and rasterized.
+    ///
+    /// * `model_matrix`: The matrix that takes the entity from object space into world space.
+    /// * `normal_matrix`: The matrix that takes the normals of the entity into world space, as
+    ///   returned by `create_normal_matrix(model_matrix)`.
+    fn shade(
+        &self,
+        vertex: &Vertex,
+        uniforms: &Uniforms,
+        model_matrix: &Mat4,
+        normal_matrix: &Mat4,
+    ) -> Vertex;
+}
+
+impl<F> VertexShader for F
+where
+    F: Fn(&Vertex, &Uniforms, &Mat4, &Mat4) -> Vertex + Send + Sync,
+{
+    fn shade(
+        &self,
+        vertex: &Vertex,
+        uniforms: &Uniforms,
+        model_matrix: &Mat4,
+        normal_matrix: &Mat4,
+    ) -> Vertex {
+        self(vertex, uniforms, model_matrix, normal_matrix)
+    }
 }
 
 pub struct Uniforms {
@@ -56,17 +76,27 @@ pub struct Uniforms {
     pub projection_matrix: Mat4,
     pub viewport_matrix: Mat4,
     pub time: f32,
+    /// Position of the camera in world space.
+    pub camera_position: Vec3,
+    pub lights: Vec<Light>,
+    pub ambient_intensity: AmbientLightIntensity,
 }
 
+/// Creates the base noise generator the built-in noise shaders configure on creation.
 pub fn create_noise() -> FastNoiseLite {
-    let mut noise = FastNoiseLite::with_seed(7082003);
+    let mut noise = FastNoiseLite::with_seed(1506);
 
-    noise.set_fractal_lacunarity(Some(0.530));
+    noise.set_frequency(Some(0.004));
 
     noise
 }
 
-pub fn vertex_shader(vertex: &Vertex, uniforms: &Uniforms, model_matrix: &Mat4) -> Vertex {
+pub fn vertex_shader(
+    vertex: &Vertex,
+    uniforms: &Uniforms,
+    model_matrix: &Mat4,
+    normal_matrix: &Mat4,
+) -> Vertex {
     let Uniforms {
         view_matrix,
         projection_matrix,
@@ -75,231 +105,39 @@ pub fn vertex_shader(vertex: &Vertex, uniforms: &Uniforms, model_matrix: &Mat4)
     } = uniforms;
 
     let position = vec4(vertex.position.x, vertex.position.y, vertex.position.z, 1.0);
-    let transformed = viewport_matrix * projection_matrix * view_matrix * model_matrix * position;
-    // println!("{position:?} TURNED INTO {transformed:?}");
+    let world_position = model_matrix * position;
+    let clip_position = projection_matrix * view_matrix * world_position;
 
-    let w = transformed.w;
-    let transformed_position = vec3(transformed.x / w, transformed.y / w, transformed.z / w);
-
-    // Transform normal
-    let vertex_normal = vec4(vertex.normal.x, vertex.normal.y, vertex.normal.z, 1.0);
-    let normal_matrix = model_matrix
-        .try_inverse()
-        .unwrap_or(Mat4::identity())
-        .transpose();
-    let transformed_normal = normal_matrix * vertex_normal;
-    let w = transformed_normal.w;
-    let transformed_normal = vec3(
-        transformed_normal.x / w,
-        transformed_normal.y / w,
-        transformed_normal.z / w,
-    );
-    // let transformed_normal = vertex.normal;
-    // println!("{normal_matrix:?} -> {transformed_normal:?}");
+    // Normals are directions, so they must not be moved by the translation of the model.
+    let normal = vec4(vertex.normal.x, vertex.normal.y, vertex.normal.z, 0.0);
+    let transformed_normal = (normal_matrix * normal).xyz().normalize();
 
     Vertex {
-        position: transformed_position,
         normal: transformed_normal,
-        tex_coords: vertex.tex_coords,
-        color: vertex.color,
+        object_position: vertex.position,
+        world_position: world_position.xyz() / world_position.w,
+        clip_position,
+        ..vertex.clone()
     }
+    .project(viewport_matrix)
 }
 
 pub fn fragment_shader(
     fragment: Fragment,
     inputs: &[EntityShader],
     uniforms: &Uniforms,
-    noise: &mut FastNoiseLite,
 ) -> Fragment {
-    let color = inputs.iter().fold(fragment.color, |acc, current| {
-        let color = match current.0 {
-            ShaderType::Stripe { stripe_width } => {
-                stripes_shader(&fragment, stripe_width, &current.1)
-            }
-            ShaderType::MovingStripes {
-                stripe_width,
-                speed,
-            } => moving_stripes(&fragment, stripe_width, speed, &current.1, uniforms),
-            ShaderType::AliveCheckerboard => todo!(),
-            ShaderType::Intensity => intensity_shader(&fragment, &acc),
-            ShaderType::BaseColor => current.1[0],
-            ShaderType::GlowShader {
-                stripe_width,
-                glow_size,
-                red,
-                blue,
-            } => glowing_shader(&fragment, stripe_width, glow_size, red, blue),
-            ShaderType::FBmShader {
-                zoom,
-                speed,
-                fractal,
-            } => fbm_shader(
-                &fragment, uniforms, &current.1, speed, zoom, &fractal, noise,
-            ),
-            ShaderType::CellularShader {
-                zoom,
-                speed,
-                fractal,
-                cellular,
-            } => cellular_shader(
-                &fragment, uniforms, &current.1, speed, zoom, &fractal, &cellular, noise,
-            ),
-        };
+    let color = inputs
+        .iter()
+        .fold(fragment.color, |acc, (shader, blend_mode)| {
+            let color = shader.shade(&fragment, &acc, uniforms);
 
-        acc.blend(&color, &current.2)
-    });
+            acc.blend(&color, blend_mode)
+        });
 
     Fragment { color, ..fragment }
 }
 
-fn cellular_shader(
-    fragment: &Fragment,
-    uniforms: &Uniforms,
-    colors: &[Color],
-    speed: f32,
-    zoom: f32,
-    fractal: &FractalConfig,
-    cellular: &CellularConfig,
-    noise: &mut FastNoiseLite,
-) -> Color {
-    let Uniforms { time, .. } = uniforms;
-    let FractalConfig {
-        octaves,
-        lacunarity,
-        gain,
-        weighted_strength,
-        f_type,
-    } = *fractal;
-    let CellularConfig {
-        distance_func,
-        return_type,
-        jitter,
-    } = *cellular;
-
-    let x = fragment.vertex_position.x * zoom + speed * time;
-    let y = fragment.vertex_position.y * zoom;
-
-    noise.set_noise_type(Some(fastnoise_lite::NoiseType::Cellular));
-    noise.set_fractal_octaves(Some(octaves));
-    noise.set_fractal_gain(Some(gain));
-    noise.set_fractal_weighted_strength(Some(weighted_strength));
-    noise.set_fractal_type(Some(f_type));
-    noise.set_fractal_lacunarity(Some(lacunarity));
-
-    noise.set_cellular_distance_function(Some(distance_func));
-    noise.set_cellular_return_type(Some(return_type));
-    noise.set_cellular_jitter(Some(jitter));
-
-    let noise_value = noise.get_noise_2d(x, y);
-    let intensity = clamp_with_universe(vec2(-1.0, 1.0), vec2(0.0, 1.0), noise_value);
-
-    colors[0] * intensity
-}
-
-fn intensity_shader(fragment: &Fragment, current_color: &Color) -> Color {
-    let Fragment { intensity, .. } = fragment;
-
-    *current_color * *intensity
-}
-
-fn stripes_shader(fragment: &Fragment, stripe_width: f32, colors: &[Color]) -> Color {
-    let y = fragment.vertex_position.y;
-    // let y = fragment.position.y as usize;
-
-    let stripe_idx = (y / stripe_width).abs() as usize % colors.len();
-    colors[stripe_idx]
-}
-
-fn interesting_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
-    let color1 = Color::red();
-    let color2 = Color::green();
-    let color3 = Color::blue();
-
-    let x = fragment.vertex_position.x;
-    let y = fragment.vertex_position.y;
-    let frequency = 10.0;
-
-    let wave1 = (x * 7.0 * frequency + y * 5.0 * frequency).sin() * 0.5 + 0.5;
-    let wave2 = (x * 5.0 * frequency - y * 8.0 * frequency + PI / 3.0).sin() * 0.5 + 0.5;
-    let wave3 = (y * 6.0 * frequency + x * 4.0 * frequency + 2.0 * PI / 3.0).sin() * 0.5 + 0.5;
-
-    // TODO: Keep implementing...
-
-    color1
-        .lerp(&color2, wave1)
-        .lerp(&color3, wave2)
-        .lerp(&color1, wave3)
-}
-
-fn glowing_shader(
-    fragment: &Fragment,
-    stripe_width: f32,
-    glow_size: f32,
-    red: f32,
-    blue: f32,
-) -> Color {
-    let y = fragment.vertex_position.y;
-
-    let distance_to_center = (y % stripe_width - stripe_width / 2.0).abs();
-    let glow_intensity = ((1.0 - (distance_to_center / glow_size).min(1.0)) * PI / 2.0).sin();
-
-    Color::new(
-        (red * glow_intensity * 255.0) as u8,
-        (blue * glow_intensity * 255.0) as u8,
-        (glow_intensity * 255.0) as u8,
-    )
-}
-
-fn moving_stripes(
-    fragment: &Fragment,
-    stripe_width: f32,
-    speed: f32,
-    colors: &[Color],
-    uniforms: &Uniforms,
-) -> Color {
-    let color1 = colors[0];
-    let color2 = colors[1];
-
-    let moving_y = fragment.vertex_position.y + uniforms.time * speed;
-
-    let stripe_factor = ((moving_y / stripe_width) * PI).sin() * 0.5 + 0.5;
-    color1.lerp(&color2, stripe_factor)
-}
-
-fn fbm_shader(
-    fragment: &Fragment,
-    uniforms: &Uniforms,
-    colors: &[Color],
-    speed: f32,
-    zoom: f32,
-    fractal: &FractalConfig,
-    noise: &mut FastNoiseLite,
-) -> Color {
-    let Uniforms { time, .. } = uniforms;
-    let FractalConfig {
-        octaves,
-        lacunarity,
-        gain,
-        weighted_strength,
-        f_type,
-    } = *fractal;
-
-    let x = fragment.vertex_position.x * zoom + speed * time;
-    let y = fragment.vertex_position.y * zoom;
-
-    noise.set_fractal_octaves(Some(octaves));
-    noise.set_fractal_gain(Some(gain));
-    noise.set_fractal_weighted_strength(Some(weighted_strength));
-    noise.set_noise_type(Some(fastnoise_lite::NoiseType::OpenSimplex2));
-    noise.set_fractal_type(Some(f_type));
-    noise.set_fractal_lacunarity(Some(lacunarity));
-
-    let noise_value = noise.get_noise_2d(x, y);
-    let intensity = clamp_with_universe(vec2(-1.0, 1.0), vec2(0.0, 1.0), noise_value);
-
-    colors[0] * intensity
-}
-
 pub fn create_model_matrix(translation: Vec3, scale: f32, rotation: Vec3) -> Mat4 {
     let (sinx, cosx) = rotation.x.sin_cos();
     let (siny, cosy) = rotation.y.sin_cos();
@@ -342,6 +180,21 @@ pub fn create_model_matrix(translation: Vec3, scale: f32, rotation: Vec3) -> Mat
     matrix
 }
 
+/// Creates the matrix that transforms the normals of an entity with the given `model_matrix`.
+///
+/// It's the inverse transpose of the rotation and scale of the model, so normals stay
+/// perpendicular to the surface even when the entity is scaled non uniformly. The translation is
+/// dropped, since normals are directions. It only depends on the model, so it should be computed
+/// once per entity and not for every vertex.
+pub fn create_normal_matrix(model_matrix: &Mat4) -> Mat4 {
+    let normal_matrix = mat4_to_mat3(model_matrix)
+        .try_inverse()
+        .unwrap_or_else(Mat3::identity)
+        .transpose();
+
+    mat3_to_mat4(&normal_matrix)
+}
+
 pub fn create_view_matrix(eye: Vec3, center: Vec3, up: Vec3) -> Mat4 {
     nalgebra_glm::look_at(&eye, &center, &up)
 }
@@ -355,13 +208,149 @@ pub fn create_projection_matrix(window_width: f32, window_height: f32) -> Mat4 {
     nalgebra_glm::perspective(fov, aspect_ratio, near, far)
 }
 
+/// Creates the matrix that maps from NDC into the framebuffer.
+///
+/// The depth is flipped so it grows towards the camera, since the z-buffer keeps the fragments
+/// with the largest depth.
 pub fn create_viewport_matrix(framebuffer_width: f32, framebuffer_height: f32) -> Mat4 {
     #[rustfmt::skip]
     let matrix = Mat4::new(
         framebuffer_width / 2.0,    0.0,                        0.0,    framebuffer_width / 2.0,
         0.0,                        -framebuffer_height / 2.0,  0.0,    framebuffer_height / 2.0,
-        0.0,                        0.0,                        1.0,    0.0,
+        0.0,                        0.0,                        -1.0,   0.0,
         0.0,                        0.0,                        0.0,    1.0);
 
     matrix
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        blenders::BlendMode,
+        fragment_shaders::{BaseColor, Intensity},
+    };
+    use nalgebra_glm::{scaling, translation, vec3, Vec2};
+
+    struct Invert;
+
+    impl FragmentShader for Invert {
+        fn shade(
+            &self,
+            _fragment: &Fragment,
+            current_color: &Color,
+            _uniforms: &Uniforms,
+        ) -> Color {
+            Color::white() - *current_color
+        }
+    }
+
+    fn uniforms(lights: Vec<Light>) -> Uniforms {
+        Uniforms {
+            view_matrix: Mat4::identity(),
+            projection_matrix: Mat4::identity(),
+            viewport_matrix: Mat4::identity(),
+            time: 0.0,
+            camera_position: Vec3::zeros(),
+            lights,
+            ambient_intensity: 0.0,
+        }
+    }
+
+    /// Transforms a vertex with the given `normal` and lights it with a single directional light
+    /// that points straight into `expected_normal`, returning the world normal and the lit color.
+    fn light_vertex(model_matrix: &Mat4, normal: Vec3, expected_normal: Vec3) -> (Vec3, Color) {
+        let uniforms = uniforms(vec![Light::directional(
+            -expected_normal,
+            Color::white(),
+            1.0,
+        )]);
+        let vertex = Vertex::new(vec3(0.5, -0.25, 1.0), normal, Vec2::zeros());
+
+        let vertex = vertex_shader(
+            &vertex,
+            &uniforms,
+            model_matrix,
+            &create_normal_matrix(model_matrix),
+        );
+        let fragment = Fragment {
+            normal: vertex.normal,
+            world_position: vertex.world_position,
+            ..Fragment::new(Vec2::zeros(), Color::white(), 0.0, vertex.object_position)
+        };
+
+        (
+            vertex.normal,
+            Intensity.shade(&fragment, &Color::white(), &uniforms),
+        )
+    }
+
+    fn assert_normal_eq(actual: Vec3, expected: Vec3) {
+        assert!(
+            (actual - expected).abs().max() < 1e-5,
+            "{actual:?} != {expected:?}"
+        );
+    }
+
+    #[test]
+    fn test_translated_entity_normals() {
+        let model_matrix = create_model_matrix(vec3(5.0, -3.0, 2.0), 1.0, Vec3::zeros());
+        let expected = vec3(0.0, 0.0, 1.0);
+
+        let (normal, color) = light_vertex(&model_matrix, vec3(0.0, 0.0, 1.0), expected);
+
+        assert_normal_eq(normal, expected);
+        assert_eq!(color, Color::white());
+    }
+
+    #[test]
+    fn test_rotated_entity_normals() {
+        // A quarter turn around Y takes +Z into +X, the scale must not change the length.
+        let model_matrix = create_model_matrix(vec3(1.0, 2.0, 3.0), 3.0, vec3(0.0, PI / 2.0, 0.0));
+        let expected = vec3(1.0, 0.0, 0.0);
+
+        let (normal, color) = light_vertex(&model_matrix, vec3(0.0, 0.0, 1.0), expected);
+
+        assert_normal_eq(normal, expected);
+        assert_eq!(color, Color::white());
+    }
+
+    #[test]
+    fn test_non_uniformly_scaled_entity_normals() {
+        // Stretching a 45° slope along X makes it shallower, so its normal leans towards Y.
+        // Transforming the normal with the model matrix would lean it towards X instead.
+        let model_matrix = translation(&vec3(0.0, 1.0, -4.0)) * scaling(&vec3(2.0, 1.0, 1.0));
+        let surface_normal = vec3(1.0, 1.0, 0.0).normalize();
+        let expected = vec3(1.0, 2.0, 0.0).normalize();
+
+        let (normal, color) = light_vertex(&model_matrix, surface_normal, expected);
+
+        assert_normal_eq(normal, expected);
+        let tangent = model_matrix * vec4(1.0, -1.0, 0.0, 0.0);
+        assert!(normal.dot(&tangent.xyz()).abs() < 1e-5);
+        assert_eq!(color, Color::white());
+    }
+
+    #[test]
+    fn test_custom_shaders_in_stack() {
+        let uniforms = uniforms(vec![]);
+        let fragment = Fragment::new(Vec2::zeros(), Color::black(), 0.0, Vec3::zeros());
+        let shaders: Vec<EntityShader> = vec![
+            (
+                Box::new(BaseColor {
+                    color: Color::new(10, 20, 30),
+                }),
+                BlendMode::Replace,
+            ),
+            (Box::new(Invert), BlendMode::Replace),
+            (
+                Box::new(|_: &Fragment, _: &Color, _: &Uniforms| Color::new(5, 5, 5)),
+                BlendMode::Subtract,
+            ),
+        ];
+
+        let fragment = fragment_shader(fragment, &shaders, &uniforms);
+
+        assert_eq!(fragment.color, Color::new(240, 230, 220));
+    }
+}
@@ -0,0 +1,201 @@
+use std::f32::consts::TAU;
+
+use nalgebra_glm::{rotate_x_vec3, vec3, Mat4, Vec3};
+
+use crate::{
+    color::Color,
+    light::Light,
+    planets::{
+        create_disco_planet, create_face_planet, create_gas_giant, create_green_planet,
+        create_ocean_planet, create_snow_planet, create_sun,
+    },
+    shader::create_model_matrix,
+    Entity,
+};
+
+/// A circular orbit around the origin, along with the spin of the body around its own axis.
+///
+/// Periods are measured in the same unit as `Uniforms::time` (milliseconds), a period of 0 keeps
+/// the body still.
+#[derive(Debug, Clone)]
+pub struct Orbit {
+    /// Distance from the origin to the body.
+    pub radius: f32,
+    /// Time it takes to go once around the origin.
+    pub period: f32,
+    /// Tilt of the orbital plane around the X axis, in radians.
+    pub inclination: f32,
+    /// Time it takes the body to spin once around its own Y axis.
+    pub spin_period: f32,
+    /// Angle along the orbit where the body starts, in radians.
+    pub phase: f32,
+    /// Uniform scale of the body.
+    pub scale: f32,
+}
+
+impl Orbit {
+    /// Computes where the body is at the given `time`.
+    /// Orbits go counter-clockwise when looked at from above.
+    pub fn position(&self, time: f32) -> Vec3 {
+        let angle = self.phase + revolutions(time, self.period) * TAU;
+        let position = vec3(angle.cos(), 0.0, -angle.sin()) * self.radius;
+
+        rotate_x_vec3(&position, self.inclination)
+    }
+
+    /// Computes the model matrix of the body at the given `time`.
+    pub fn model_matrix(&self, time: f32) -> Mat4 {
+        let spin = revolutions(time, self.spin_period) * TAU;
+
+        create_model_matrix(self.position(time), self.scale, vec3(0.0, spin, 0.0))
+    }
+}
+
+/// The amount of revolutions (possibly partial) done after `time` with the given `period`.
+fn revolutions(time: f32, period: f32) -> f32 {
+    if period == 0.0 {
+        0.0
+    } else {
+        (time / period).fract()
+    }
+}
+
+/// Moves every entity that has an orbit to where it should be at the given `time`.
+pub fn update_orbits(entities: &mut [Entity], time: f32) {
+    for entity in entities {
+        if let Some(orbit) = &entity.orbit {
+            entity.model_matrix = orbit.model_matrix(time);
+        }
+    }
+}
+
+/// Creates the light emitted by the sun of the solar system, which sits at the origin.
+pub fn create_sun_light() -> Light {
+    Light::point(Vec3::zeros(), Color::white(), 1.0)
+}
+
+/// Creates the sun and every planet preset, each one placed on its own orbit.
+pub fn create_solar_system() -> Vec<Entity> {
+    let bodies = [
+        (create_sun(), 0.0, 0.0, 0.0, 40_000.0, 3.0),
+        (create_disco_planet(), 6.0, 12_000.0, 0.05, 4_000.0, 0.4),
+        (create_face_planet(), 9.0, 18_000.0, -0.08, 6_000.0, 0.6),
+        (create_ocean_planet(), 12.5, 26_000.0, 0.02, 5_000.0, 0.8),
+        (create_green_planet(), 16.0, 34_000.0, 0.1, 5_500.0, 0.7),
+        (create_gas_giant(), 21.0, 50_000.0, -0.04, 3_000.0, 1.6),
+        (create_snow_planet(), 27.0, 70_000.0, 0.12, 7_000.0, 0.9),
+    ];
+    let count = bodies.len() as f32;
+
+    bodies
+        .into_iter()
+        .enumerate()
+        .map(
+            |(i, (entity, radius, period, inclination, spin_period, scale))| {
+                let orbit = Orbit {
+                    radius,
+                    period,
+                    inclination,
+                    spin_period,
+                    // Spread the planets so they don't start aligned.
+                    phase: i as f32 / count * TAU,
+                    scale,
+                };
+
+                Entity {
+                    model_matrix: orbit.model_matrix(0.0),
+                    orbit: Some(orbit),
+                    ..entity
+                }
+            },
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assets::ObjHandle, shader::vertex_shader};
+    use nalgebra_glm::vec4;
+
+    fn orbit(period: f32, inclination: f32) -> Orbit {
+        Orbit {
+            radius: 10.0,
+            period,
+            inclination,
+            spin_period: 1000.0,
+            phase: 0.0,
+            scale: 2.0,
+        }
+    }
+
+    fn assert_vec_eq(actual: Vec3, expected: Vec3) {
+        assert!(
+            (actual - expected).abs().max() < 1e-3,
+            "{actual:?} != {expected:?}"
+        );
+    }
+
+    #[test]
+    fn test_orbit_position_over_time() {
+        let orbit = orbit(4000.0, 0.0);
+
+        assert_vec_eq(orbit.position(0.0), vec3(10.0, 0.0, 0.0));
+        assert_vec_eq(orbit.position(1000.0), vec3(0.0, 0.0, -10.0));
+        assert_vec_eq(orbit.position(2000.0), vec3(-10.0, 0.0, 0.0));
+        assert_vec_eq(orbit.position(4000.0), vec3(10.0, 0.0, 0.0));
+        assert_vec_eq(orbit.position(41_000.0), orbit.position(1000.0));
+    }
+
+    #[test]
+    fn test_inclined_orbit_leaves_the_plane() {
+        let orbit = orbit(4000.0, 0.3);
+
+        // The nodes of the orbit stay on the X axis, the rest is tilted out of the XZ plane.
+        assert_vec_eq(orbit.position(0.0), vec3(10.0, 0.0, 0.0));
+        let top = orbit.position(1000.0);
+        assert_vec_eq(top, vec3(0.0, 0.3_f32.sin() * 10.0, -0.3_f32.cos() * 10.0));
+        assert!((top.magnitude() - 10.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_still_orbit() {
+        let orbit = Orbit {
+            radius: 0.0,
+            ..orbit(0.0, 0.0)
+        };
+
+        assert_vec_eq(orbit.position(12_345.0), Vec3::zeros());
+    }
+
+    #[test]
+    fn test_model_matrix_spins_and_scales_the_body() {
+        let orbit = orbit(4000.0, 0.0);
+
+        // A quarter of a spin takes +Z into +X.
+        let matrix = orbit.model_matrix(250.0);
+        let center = matrix * vec4(0.0, 0.0, 0.0, 1.0);
+        let front = matrix * vec4(0.0, 0.0, 1.0, 1.0);
+
+        assert_vec_eq(center.xyz(), orbit.position(250.0));
+        assert_vec_eq(front.xyz() - center.xyz(), vec3(2.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_update_orbits() {
+        let entity = |orbit| Entity {
+            objs: ObjHandle::default(),
+            vertex_shader: Box::new(vertex_shader),
+            shaders: vec![],
+            model_matrix: Mat4::identity(),
+            orbit,
+        };
+        let mut entities = [entity(Some(orbit(4000.0, 0.0))), entity(None)];
+
+        update_orbits(&mut entities, 1000.0);
+
+        let center = entities[0].model_matrix * vec4(0.0, 0.0, 0.0, 1.0);
+        assert_vec_eq(center.xyz(), vec3(0.0, 0.0, -10.0));
+        assert_eq!(entities[1].model_matrix, Mat4::identity());
+    }
+}
@@ -0,0 +1,315 @@
+//! Meshes of STL files, both binary and ASCII, as exported by CAD programs and 3D scanners.
+//!
+//! STL stores every triangle on its own, so vertices on the same position are merged into one,
+//! and the normals are generated with `normals::generate_normals` since many exporters leave the
+//! facet normals empty. Meshes are saved as binary STL.
+
+use std::{
+    fmt::Display,
+    fs,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use nalgebra_glm::{cross, vec3, Vec2, Vec3};
+
+use crate::{
+    normals::{generate_normals, position_key, NormalStrategy},
+    obj::Mesh,
+    vertex::Vertex,
+};
+
+/// The size of the header of binary STL files, before the triangle count.
+const HEADER_SIZE: usize = 80;
+/// The size of every triangle of binary STL files: its normal, 3 vertices and a `u16` attribute.
+const TRIANGLE_SIZE: usize = 12 * 4 + 2;
+
+#[derive(Debug)]
+pub enum StlError {
+    /// The STL file couldn't be read.
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    /// The file is neither a valid binary nor a valid ASCII STL.
+    Parse { message: String },
+    /// The file has no triangles.
+    EmptyMesh,
+}
+
+impl Display for StlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StlError::Io { path, error } => {
+                write!(f, "Couldn't read STL `{}`: {error}", path.display())
+            }
+            StlError::Parse { message } => write!(f, "Malformed STL: {message}"),
+            StlError::EmptyMesh => write!(f, "The STL has no triangles"),
+        }
+    }
+}
+
+impl std::error::Error for StlError {}
+
+/// Loads the mesh of the STL file at `path`.
+pub fn load_stl(path: impl AsRef<Path>) -> Result<Mesh, StlError> {
+    let path = path.as_ref();
+    let source = fs::read(path).map_err(|error| StlError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+
+    parse_stl(&source)
+}
+
+/// Parses a binary or ASCII STL file.
+///
+/// Binary files may also start with `solid`, so a file is only parsed as ASCII when its size
+/// doesn't match the triangle count of a binary file.
+pub fn parse_stl(source: &[u8]) -> Result<Mesh, StlError> {
+    let triangles = if is_binary(source) {
+        parse_binary(source)
+    } else {
+        let source = std::str::from_utf8(source).map_err(|_| StlError::Parse {
+            message: "It isn't binary, nor ASCII text".to_string(),
+        })?;
+        parse_ascii(source)?
+    };
+
+    if triangles.is_empty() {
+        return Err(StlError::EmptyMesh);
+    }
+
+    Ok(merge_vertices(&triangles))
+}
+
+fn is_binary(source: &[u8]) -> bool {
+    let Some(count) = source.get(HEADER_SIZE..HEADER_SIZE + 4) else {
+        return false;
+    };
+    let count = u32::from_le_bytes(count.try_into().unwrap()) as usize;
+
+    !source.starts_with(b"solid")
+        || count
+            .checked_mul(TRIANGLE_SIZE)
+            .is_some_and(|size| source.len() == HEADER_SIZE + 4 + size)
+}
+
+fn parse_binary(source: &[u8]) -> Vec<[Vec3; 3]> {
+    let read_vec3 = |bytes: &[u8]| {
+        let [x, y, z] = [0, 4, 8].map(|i| f32::from_le_bytes(bytes[i..i + 4].try_into().unwrap()));
+        vec3(x, y, z)
+    };
+
+    // Truncated files keep the triangles that are complete.
+    source[HEADER_SIZE + 4..]
+        .chunks_exact(TRIANGLE_SIZE)
+        .map(|triangle| [12, 24, 36].map(|offset| read_vec3(&triangle[offset..])))
+        .collect()
+}
+
+fn parse_ascii(source: &str) -> Result<Vec<[Vec3; 3]>, StlError> {
+    let mut triangles = vec![];
+    let mut vertices = vec![];
+
+    for (idx, line) in source.lines().enumerate() {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("vertex") => {
+                let coordinates: Vec<f32> = words
+                    .map(str::parse)
+                    .collect::<Result<_, _>>()
+                    .map_err(|error| StlError::Parse {
+                        message: format!("Line {}: {error}", idx + 1),
+                    })?;
+                let [x, y, z] = coordinates[..] else {
+                    return Err(StlError::Parse {
+                        message: format!("Line {}: A vertex needs 3 coordinates", idx + 1),
+                    });
+                };
+                vertices.push(vec3(x, y, z));
+            }
+            Some("endloop") => {
+                let [a, b, c] = vertices[..] else {
+                    return Err(StlError::Parse {
+                        message: format!("Line {}: A facet needs 3 vertices", idx + 1),
+                    });
+                };
+                triangles.push([a, b, c]);
+                vertices.clear();
+            }
+            // The facet normals are generated again, and the rest only gives structure.
+            _ => {}
+        }
+    }
+
+    Ok(triangles)
+}
+
+/// Saves the `mesh` into a binary STL file at `path`.
+pub fn save_stl(path: impl AsRef<Path>, mesh: &Mesh) -> Result<(), StlError> {
+    let path = path.as_ref();
+    let io_error = |error| StlError::Io {
+        path: path.to_path_buf(),
+        error,
+    };
+
+    let mut writer = BufWriter::new(fs::File::create(path).map_err(io_error)?);
+    write_stl(&mut writer, mesh).map_err(io_error)?;
+    writer.flush().map_err(io_error)
+}
+
+/// Writes the `mesh` in the binary STL format.
+///
+/// Only the positions are kept, every facet gets the normal of its triangle.
+pub fn write_stl(writer: &mut impl Write, mesh: &Mesh) -> io::Result<()> {
+    let mut header = b"binary STL".to_vec();
+    header.resize(HEADER_SIZE, 0);
+    writer.write_all(&header)?;
+    writer.write_all(&(mesh.triangle_count() as u32).to_le_bytes())?;
+
+    for [a, b, c] in mesh.triangles(&mesh.vertices) {
+        let (a, b, c) = (a.position, b.position, c.position);
+        let normal = cross(&(b - a), &(c - a))
+            .try_normalize(f32::EPSILON)
+            .unwrap_or_else(Vec3::zeros);
+        for v in [normal, a, b, c] {
+            for coordinate in [v.x, v.y, v.z] {
+                writer.write_all(&coordinate.to_le_bytes())?;
+            }
+        }
+        // The attribute byte count, unused.
+        writer.write_all(&[0, 0])?;
+    }
+
+    Ok(())
+}
+
+/// Creates an indexed mesh out of the triangles, sharing the vertices on the same position.
+fn merge_vertices(triangles: &[[Vec3; 3]]) -> Mesh {
+    let mut mesh = Mesh::default();
+    let mut shared = std::collections::HashMap::new();
+
+    for position in triangles.iter().flatten() {
+        let idx = *shared.entry(position_key(position)).or_insert_with(|| {
+            mesh.vertices
+                .push(Vertex::new(*position, Vec3::zeros(), Vec2::zeros()));
+            mesh.vertices.len() as u32 - 1
+        });
+        mesh.indices.push(idx);
+    }
+
+    generate_normals(&mut mesh, NormalStrategy::default());
+
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::icosphere;
+
+    fn to_binary(mesh: &Mesh) -> Vec<u8> {
+        // Binary files starting with `solid` must not be taken for ASCII.
+        let mut stl = b"solid but binary".to_vec();
+        stl.resize(HEADER_SIZE, 0);
+        stl.extend((mesh.triangle_count() as u32).to_le_bytes());
+        for triangle in mesh.triangles(&mesh.vertices) {
+            stl.extend([0.0f32; 3].iter().flat_map(|c| c.to_le_bytes()));
+            for vertex in triangle {
+                let p = vertex.position;
+                stl.extend([p.x, p.y, p.z].iter().flat_map(|c| c.to_le_bytes()));
+            }
+            stl.extend([0, 0]);
+        }
+
+        stl
+    }
+
+    fn to_ascii(mesh: &Mesh) -> String {
+        let mut stl = "solid sphere\n".to_string();
+        for triangle in mesh.triangles(&mesh.vertices) {
+            stl += "  facet normal 0 0 0\n    outer loop\n";
+            for vertex in triangle {
+                let p = vertex.position;
+                stl += &format!("      vertex {} {} {}\n", p.x, p.y, p.z);
+            }
+            stl += "    endloop\n  endfacet\n";
+        }
+
+        stl + "endsolid sphere\n"
+    }
+
+    fn assert_same_triangles(mesh: &Mesh, expected: &Mesh) {
+        assert_eq!(mesh.triangle_count(), expected.triangle_count());
+        let positions = |mesh: &Mesh| {
+            mesh.triangles(&mesh.vertices)
+                .map(|triangle| triangle.map(|v| v.position))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(positions(mesh), positions(expected));
+    }
+
+    #[test]
+    fn test_binary_and_ascii() {
+        let sphere = icosphere(2);
+        // The icosphere splits the vertices on the seam, STL can't.
+        let positions = 10 * 4usize.pow(2) + 2;
+
+        for stl in [to_binary(&sphere), to_ascii(&sphere).into_bytes()] {
+            let mesh = parse_stl(&stl).unwrap();
+
+            assert_same_triangles(&mesh, &sphere);
+            assert_eq!(mesh.vertices.len(), positions);
+            for vertex in &mesh.vertices {
+                assert!((vertex.normal - vertex.position).magnitude() < 0.05);
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let sphere = icosphere(2);
+        let path = crate::test_utils::temp_path("round_trip.stl");
+
+        save_stl(&path, &sphere).unwrap();
+        let loaded = load_stl(&path);
+        fs::remove_file(path).unwrap();
+        let loaded = loaded.unwrap();
+
+        // Vertices split on the seam of the icosphere come back as one, in order of appearance.
+        let mut shared = std::collections::HashMap::new();
+        let expected_indices: Vec<u32> = sphere
+            .indices
+            .iter()
+            .map(|&idx| {
+                let next = shared.len() as u32;
+                *shared
+                    .entry(position_key(&sphere.vertices[idx as usize].position))
+                    .or_insert(next)
+            })
+            .collect();
+        assert_same_triangles(&loaded, &sphere);
+        assert_eq!(loaded.indices, expected_indices);
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert!(matches!(
+            parse_stl(b"solid empty\nendsolid empty\n"),
+            Err(StlError::EmptyMesh)
+        ));
+        assert!(matches!(
+            parse_stl(b"solid broken\nfacet normal 0 0 1\nouter loop\nvertex 0 0\nendloop"),
+            Err(StlError::Parse { .. })
+        ));
+        assert!(matches!(
+            parse_stl(b"solid broken\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nendloop"),
+            Err(StlError::Parse { .. })
+        ));
+        assert!(matches!(
+            load_stl("does_not_exist.stl"),
+            Err(StlError::Io { .. })
+        ));
+    }
+}
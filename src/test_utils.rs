@@ -0,0 +1,20 @@
+//! Helpers shared by the tests of several modules.
+
+use std::path::{Path, PathBuf};
+
+/// A path in the temporary directory for the file `file_name`, with the id of the process added
+/// to its name so test runs at the same time don't overwrite each other's files.
+pub fn temp_path(file_name: &str) -> PathBuf {
+    let file_name = Path::new(file_name);
+    let stem = file_name.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match file_name.extension() {
+        Some(extension) => format!(
+            "{stem}_{}.{}",
+            std::process::id(),
+            extension.to_string_lossy()
+        ),
+        None => format!("{stem}_{}", std::process::id()),
+    };
+
+    std::env::temp_dir().join(name)
+}
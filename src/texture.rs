@@ -0,0 +1,425 @@
+use std::{io, path::Path};
+
+use nalgebra_glm::Vec2;
+
+use crate::{bmp::read_bmp_file, color::Color};
+
+/// How texture coordinates outside of `[0, 1]` are mapped into the texture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WrapMode {
+    /// The texture tiles endlessly.
+    #[default]
+    Repeat,
+    /// The texels on the border stretch outwards.
+    Clamp,
+    /// The texture tiles, flipping every other copy so the borders match.
+    Mirror,
+}
+
+impl WrapMode {
+    /// Maps a texel index into the `0..size` range.
+    fn wrap(self, idx: isize, size: usize) -> usize {
+        let size = size as isize;
+
+        let idx = match self {
+            WrapMode::Repeat => idx.rem_euclid(size),
+            WrapMode::Clamp => idx.clamp(0, size - 1),
+            WrapMode::Mirror => {
+                let idx = idx.rem_euclid(2 * size);
+                if idx < size {
+                    idx
+                } else {
+                    2 * size - 1 - idx
+                }
+            }
+        };
+
+        idx as usize
+    }
+}
+
+/// How the texels around the sampled point are combined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Filter {
+    /// The texel the point falls into, which looks blocky up close.
+    Nearest,
+    /// A weighted average of the 4 texels closest to the point.
+    Bilinear,
+    /// Bilinear samples of the 2 mip levels closest to the size of the pixel on the texture,
+    /// blended together so far away surfaces don't alias.
+    #[default]
+    Trilinear,
+    /// Up to `MAX_ANISOTROPY` trilinear samples along the longest side of the pixel on the
+    /// texture, so surfaces seen at a grazing angle stay sharp across the other side.
+    Anisotropic,
+}
+
+/// The most samples `Filter::Anisotropic` takes for a single fragment.
+pub const MAX_ANISOTROPY: usize = 16;
+
+/// One image of the mip chain of a texture.
+#[derive(Debug, Clone)]
+struct MipLevel {
+    width: usize,
+    height: usize,
+    /// The colors of the texels, row by row from the top left corner like `Framebuffer::buffer`.
+    texels: Vec<[f32; 3]>,
+}
+
+impl MipLevel {
+    /// Creates the next level of the chain, half the size and each texel the average of the
+    /// 2x2 texels it covers. Texels on the last row or column of odd sizes are repeated.
+    fn downsample(&self) -> Self {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let texel = |x: usize, y: usize| {
+            self.texels[y.min(self.height - 1) * self.width + x.min(self.width - 1)]
+        };
+
+        let texels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let block = [
+                    texel(2 * x, 2 * y),
+                    texel(2 * x + 1, 2 * y),
+                    texel(2 * x, 2 * y + 1),
+                    texel(2 * x + 1, 2 * y + 1),
+                ];
+                [0, 1, 2].map(|i| block.iter().map(|texel| texel[i]).sum::<f32>() / 4.0)
+            })
+            .collect();
+
+        MipLevel {
+            width,
+            height,
+            texels,
+        }
+    }
+
+    fn texel(&self, x: isize, y: isize, wrap: WrapMode) -> [f32; 3] {
+        self.texels[wrap.wrap(y, self.height) * self.width + wrap.wrap(x, self.width)]
+    }
+
+    fn nearest(&self, tex_coords: &Vec2, wrap: WrapMode) -> [f32; 3] {
+        let (x, y) = self.to_texels(tex_coords);
+        self.texel(x.floor() as isize, y.floor() as isize, wrap)
+    }
+
+    fn bilinear(&self, tex_coords: &Vec2, wrap: WrapMode) -> [f32; 3] {
+        // Texel centers are at half coordinates.
+        let (x, y) = self.to_texels(tex_coords);
+        let (x, y) = (x - 0.5, y - 0.5);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as isize, y0 as isize);
+
+        let [top_left, top_right, bottom_left, bottom_right] =
+            [(x0, y0), (x0 + 1, y0), (x0, y0 + 1), (x0 + 1, y0 + 1)]
+                .map(|(x, y)| self.texel(x, y, wrap));
+        let top = lerp(top_left, top_right, tx);
+        let bottom = lerp(bottom_left, bottom_right, tx);
+
+        lerp(top, bottom, ty)
+    }
+
+    /// Converts texture coordinates into texel coordinates, where rows go from the top down.
+    fn to_texels(&self, tex_coords: &Vec2) -> (f32, f32) {
+        (
+            tex_coords.x * self.width as f32,
+            (1.0 - tex_coords.y) * self.height as f32,
+        )
+    }
+}
+
+/// An image sampled through texture coordinates.
+///
+/// The texture coordinates (0, 0) are on the bottom left corner of the image and (1, 1) on the
+/// top right one, like in OBJ files. The mip chain, down to a single texel, is built when the
+/// texture is created.
+#[derive(Debug, Clone)]
+pub struct Texture {
+    pub width: usize,
+    pub height: usize,
+    /// The image itself first, then every level half the size of the previous one.
+    mips: Vec<MipLevel>,
+}
+
+impl Texture {
+    /// Creates a texture out of the colors in `buffer`, laid out like `Framebuffer::buffer`.
+    ///
+    /// Panics if the size of the buffer doesn't match the dimensions, or they are 0.
+    pub fn new(width: usize, height: usize, buffer: &[u32]) -> Self {
+        assert!(width > 0 && height > 0, "A texture can't be empty");
+        assert_eq!(buffer.len(), width * height);
+
+        let mut mips = vec![MipLevel {
+            width,
+            height,
+            texels: buffer.iter().map(|&c| to_rgb(Color::from(c))).collect(),
+        }];
+        while let Some(last) = mips.last().filter(|mip| mip.width > 1 || mip.height > 1) {
+            mips.push(last.downsample());
+        }
+
+        Texture {
+            width,
+            height,
+            mips,
+        }
+    }
+
+    /// Loads the image at `path` as a texture. Only BMP images are supported.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let is_bmp = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("bmp"));
+        if !is_bmp {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("`{}` isn't a BMP image", path.display()),
+            ));
+        }
+
+        let (width, height, buffer) = read_bmp_file(path)?;
+
+        Ok(Texture::new(width, height, &buffer))
+    }
+
+    /// The number of levels of the mip chain, including the image itself.
+    pub fn mip_levels(&self) -> usize {
+        self.mips.len()
+    }
+
+    /// The color of the texel at (x, y), counting from the top left corner.
+    pub fn texel(&self, x: usize, y: usize) -> Color {
+        to_color(self.mips[0].texels[y * self.width + x])
+    }
+
+    /// Samples the texture at the given texture coordinates, as if the pixel was a single point.
+    /// Every filter uses the full size image.
+    pub fn sample(&self, tex_coords: &Vec2, filter: Filter, wrap: WrapMode) -> Color {
+        self.sample_grad(tex_coords, &Vec2::zeros(), &Vec2::zeros(), filter, wrap)
+    }
+
+    /// Samples the texture at the given texture coordinates, where `dx` and `dy` are how much they
+    /// change from one pixel to the next along the screen axes, like `Fragment::tex_coords_dx`.
+    ///
+    /// The derivatives give the footprint of the pixel on the texture, which picks the mip level
+    /// for `Filter::Trilinear` and the direction of the samples for `Filter::Anisotropic`.
+    pub fn sample_grad(
+        &self,
+        tex_coords: &Vec2,
+        dx: &Vec2,
+        dy: &Vec2,
+        filter: Filter,
+        wrap: WrapMode,
+    ) -> Color {
+        let size = Vec2::new(self.width as f32, self.height as f32);
+        // The sides of the footprint, measured in texels of the full size image.
+        let (dx_texels, dy_texels) = (dx.component_mul(&size), dy.component_mul(&size));
+
+        let rgb = match filter {
+            Filter::Nearest => self.mips[0].nearest(tex_coords, wrap),
+            Filter::Bilinear => self.mips[0].bilinear(tex_coords, wrap),
+            Filter::Trilinear => {
+                let footprint = dx_texels.magnitude().max(dy_texels.magnitude());
+                self.trilinear(tex_coords, footprint, wrap)
+            }
+            Filter::Anisotropic => {
+                let (major, major_texels, minor_texels) =
+                    if dx_texels.magnitude() >= dy_texels.magnitude() {
+                        (dx, dx_texels.magnitude(), dy_texels.magnitude())
+                    } else {
+                        (dy, dy_texels.magnitude(), dx_texels.magnitude())
+                    };
+                let samples = (major_texels / minor_texels.max(f32::EPSILON))
+                    .ceil()
+                    .clamp(1.0, MAX_ANISOTROPY as f32) as usize;
+                // Every sample covers its share of the major side.
+                let footprint = (major_texels / samples as f32).max(minor_texels);
+
+                let total = (0..samples)
+                    .map(|i| {
+                        let offset = (i as f32 + 0.5) / samples as f32 - 0.5;
+                        self.trilinear(&(tex_coords + major * offset), footprint, wrap)
+                    })
+                    .fold([0.0; 3], |total, rgb| [0, 1, 2].map(|i| total[i] + rgb[i]));
+                total.map(|c| c / samples as f32)
+            }
+        };
+
+        to_color(rgb)
+    }
+
+    /// Blends the bilinear samples of the 2 mip levels whose texels are closest to `footprint`
+    /// texels of the full size image.
+    fn trilinear(&self, tex_coords: &Vec2, footprint: f32, wrap: WrapMode) -> [f32; 3] {
+        let max_level = (self.mips.len() - 1) as f32;
+        let level = footprint.max(1.0).log2().min(max_level);
+        let (lower, t) = (level.floor(), level.fract());
+
+        let near = self.mips[lower as usize].bilinear(tex_coords, wrap);
+        if t == 0.0 {
+            near
+        } else {
+            let far = self.mips[lower as usize + 1].bilinear(tex_coords, wrap);
+            lerp(near, far, t)
+        }
+    }
+}
+
+fn to_rgb(color: Color) -> [f32; 3] {
+    [color.r, color.g, color.b].map(f32::from)
+}
+
+fn to_color(rgb: [f32; 3]) -> Color {
+    let [r, g, b] = rgb.map(|c| c.round().clamp(0.0, 255.0) as u8);
+    Color::new(r, g, b)
+}
+
+fn lerp(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    [0, 1, 2].map(|i| a[i] + (b[i] - a[i]) * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra_glm::vec2;
+
+    /// A 2x2 texture, black and white on the top row and red and blue on the bottom one.
+    fn checker() -> Texture {
+        Texture::new(2, 2, &[0x000000, 0xffffff, 0xff0000, 0x0000ff])
+    }
+
+    #[test]
+    fn test_nearest() {
+        let texture = checker();
+        let sample = |u, v| texture.sample(&vec2(u, v), Filter::Nearest, WrapMode::Repeat);
+
+        assert_eq!(sample(0.25, 0.75), Color::black());
+        assert_eq!(sample(0.75, 0.75), Color::white());
+        assert_eq!(sample(0.25, 0.25), Color::red());
+        assert_eq!(sample(0.75, 0.25), Color::blue());
+    }
+
+    #[test]
+    fn test_bilinear() {
+        let texture = checker();
+        let sample = |u, v| texture.sample(&vec2(u, v), Filter::Bilinear, WrapMode::Clamp);
+
+        // On the texel centers the texture is exact.
+        assert_eq!(sample(0.25, 0.75), Color::black());
+        assert_eq!(sample(0.75, 0.25), Color::blue());
+        // Halfway between the top texels.
+        assert_eq!(sample(0.5, 0.75), Color::new(128, 128, 128));
+        // The average of all 4 texels.
+        assert_eq!(sample(0.5, 0.5), Color::new(128, 64, 128));
+    }
+
+    #[test]
+    fn test_wrap_modes() {
+        let texture = checker();
+        let sample = |u, wrap| texture.sample(&vec2(u, 0.75), Filter::Nearest, wrap);
+
+        // Just past the right border.
+        assert_eq!(sample(1.25, WrapMode::Repeat), Color::black());
+        assert_eq!(sample(1.25, WrapMode::Clamp), Color::white());
+        assert_eq!(sample(1.25, WrapMode::Mirror), Color::white());
+        // Past a whole mirrored copy.
+        assert_eq!(sample(2.25, WrapMode::Mirror), Color::black());
+        assert_eq!(sample(-0.25, WrapMode::Repeat), Color::white());
+        assert_eq!(sample(-0.25, WrapMode::Clamp), Color::black());
+        assert_eq!(sample(-0.25, WrapMode::Mirror), Color::black());
+    }
+
+    #[test]
+    fn test_bilinear_wraps_across_borders() {
+        let texture = checker();
+
+        // On the left border, halfway between the last and the first columns.
+        let repeat = texture.sample(&vec2(0.0, 0.75), Filter::Bilinear, WrapMode::Repeat);
+        let clamp = texture.sample(&vec2(0.0, 0.75), Filter::Bilinear, WrapMode::Clamp);
+
+        assert_eq!(repeat, Color::new(128, 128, 128));
+        assert_eq!(clamp, Color::black());
+    }
+
+    /// An 8x8 texture of horizontal stripes, a row black and the next white.
+    fn stripes() -> Texture {
+        let buffer: Vec<u32> = (0..8)
+            .flat_map(|y| [if y % 2 == 0 { 0x000000 } else { 0xffffff }; 8])
+            .collect();
+        Texture::new(8, 8, &buffer)
+    }
+
+    #[test]
+    fn test_mip_chain() {
+        let texture = Texture::new(4, 2, &[0xff0000, 0xff0000, 0x0000ff, 0x0000ff].repeat(2));
+
+        let sizes: Vec<_> = texture.mips.iter().map(|m| (m.width, m.height)).collect();
+        assert_eq!(sizes, [(4, 2), (2, 1), (1, 1)]);
+        assert_eq!(texture.mip_levels(), 3);
+        assert_eq!(to_color(texture.mips[1].texels[0]), Color::red());
+        assert_eq!(to_color(texture.mips[2].texels[0]), Color::new(128, 0, 128));
+        // A single texel is its own chain.
+        assert_eq!(Texture::new(1, 1, &[0x123456]).mip_levels(), 1);
+    }
+
+    #[test]
+    fn test_trilinear_picks_level_from_derivatives() {
+        let texture = stripes();
+        let sample = |d: f32| {
+            texture.sample_grad(
+                &vec2(0.5, 0.5625),
+                &vec2(d, 0.0),
+                &vec2(0.0, d),
+                Filter::Trilinear,
+                WrapMode::Repeat,
+            )
+        };
+
+        // A pixel the size of a texel sees the stripe it falls in.
+        assert_eq!(sample(1.0 / 8.0), Color::white());
+        // A pixel covering the whole texture sees the average.
+        assert_eq!(sample(1.0), Color::new(128, 128, 128));
+        // Halfway between levels the stripe fades halfway into the average of the level below.
+        let between = sample(2f32.sqrt() / 8.0);
+        assert!(between.r > 128 && between.r < 255, "{between:?}");
+    }
+
+    #[test]
+    fn test_anisotropic_keeps_detail_across_the_footprint() {
+        let texture = stripes();
+        // The pixel is stretched 8 texels along the stripes, 1 texel across them.
+        let sample = |filter| {
+            texture.sample_grad(
+                &vec2(0.5, 0.5625),
+                &vec2(1.0, 0.0),
+                &vec2(0.0, 1.0 / 8.0),
+                filter,
+                WrapMode::Repeat,
+            )
+        };
+
+        assert_eq!(sample(Filter::Trilinear), Color::new(128, 128, 128));
+        assert_eq!(sample(Filter::Anisotropic), Color::white());
+    }
+
+    #[test]
+    fn test_load_bmp() {
+        let path = crate::test_utils::temp_path("texture.bmp");
+        // 3 texels wide, so every row is padded.
+        let buffer = [0xff0000, 0x00ff00, 0x0000ff, 0x123456, 0x654321, 0xabcdef];
+        crate::bmp::write_bmp_file(path.to_str().unwrap(), &buffer, 3, 2).unwrap();
+
+        let texture = Texture::load(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!((texture.width, texture.height), (3, 2));
+        assert_eq!(texture.texel(0, 0), Color::red());
+        assert_eq!(texture.texel(2, 0), Color::blue());
+        assert_eq!(texture.texel(1, 1), Color::from(0x654321));
+        assert!(Texture::load("cube.obj").is_err());
+    }
+}
@@ -1,5 +1,5 @@
 use crate::color::Color;
-use nalgebra_glm::{Vec2, Vec3};
+use nalgebra_glm::{vec3, vec4, Mat4, Vec2, Vec3, Vec4};
 
 #[derive(Clone, Debug)]
 pub struct Vertex {
@@ -7,6 +7,15 @@ pub struct Vertex {
     pub normal: Vec3,
     pub tex_coords: Vec2,
     pub color: Color,
+    /// The position of the vertex in object space, as it was loaded from the model.
+    pub object_position: Vec3,
+    /// The position of the vertex in world space, after applying the model matrix.
+    pub world_position: Vec3,
+    /// The position of the vertex in clip space, before the perspective divide.
+    pub clip_position: Vec4,
+    /// The reciprocal of the clip space W, used for perspective correct interpolation.
+    /// Untransformed vertices have a value of 1.
+    pub inv_w: f32,
 }
 
 impl Vertex {
@@ -16,6 +25,10 @@ impl Vertex {
             normal,
             tex_coords,
             color: Color::black(),
+            object_position: position,
+            world_position: position,
+            clip_position: vec4(position.x, position.y, position.z, 1.0),
+            inv_w: 1.0,
         }
     }
 
@@ -25,6 +38,10 @@ impl Vertex {
             normal: Vec3::new(0.0, 0.0, 0.0),
             tex_coords: Vec2::new(0.0, 0.0),
             color,
+            object_position: position,
+            world_position: position,
+            clip_position: vec4(position.x, position.y, position.z, 1.0),
+            inv_w: 1.0,
         }
     }
 
@@ -32,6 +49,35 @@ impl Vertex {
         self.position = position;
         self.normal = normal;
     }
+
+    /// Applies the perspective divide to `clip_position` and maps the result into screen space.
+    ///
+    /// * `viewport_matrix`: The matrix that maps from NDC into the framebuffer.
+    pub fn project(self, viewport_matrix: &Mat4) -> Vertex {
+        let screen = viewport_matrix * self.clip_position;
+        let w = self.clip_position.w;
+
+        Vertex {
+            position: vec3(screen.x / w, screen.y / w, screen.z / w),
+            inv_w: 1.0 / w,
+            ..self
+        }
+    }
+
+    /// Linearly interpolates every attribute between this vertex and `other`.
+    /// A `t` of 0 returns this vertex and a `t` of 1 returns `other`.
+    pub fn lerp(&self, other: &Vertex, t: f32) -> Vertex {
+        Vertex {
+            position: self.position.lerp(&other.position, t),
+            normal: self.normal.lerp(&other.normal, t),
+            tex_coords: self.tex_coords.lerp(&other.tex_coords, t),
+            color: self.color.lerp(&other.color, t),
+            object_position: self.object_position.lerp(&other.object_position, t),
+            world_position: self.world_position.lerp(&other.world_position, t),
+            clip_position: self.clip_position.lerp(&other.clip_position, t),
+            inv_w: self.inv_w + (other.inv_w - self.inv_w) * t,
+        }
+    }
 }
 
 impl Default for Vertex {
@@ -41,6 +87,10 @@ impl Default for Vertex {
             normal: Vec3::new(0.0, 1.0, 0.0),
             tex_coords: Vec2::new(0.0, 0.0),
             color: Color::black(),
+            object_position: Vec3::new(0.0, 0.0, 0.0),
+            world_position: Vec3::new(0.0, 0.0, 0.0),
+            clip_position: vec4(0.0, 0.0, 0.0, 1.0),
+            inv_w: 1.0,
         }
     }
 }